@@ -0,0 +1,111 @@
+//! Timing benchmarks of the common swap paths, run in-process through `swap::swap` with criterion.
+//!
+//! Run with `cargo bench --bench swap`. Every benchmark swaps the same two items back and forth,
+//! so the fixture is back in its initial state after an even number of iterations.
+//!
+//! To guard against regressions, save a baseline on the reference revision and compare later runs:
+//!
+//! ```text
+//! cargo bench --bench swap -- --save-baseline main
+//! cargo bench --bench swap -- --baseline main
+//! ```
+//!
+//! Criterion then reports, for each benchmark, whether it got slower than the baseline beyond its
+//! noise threshold (`--noise-threshold`, 1% by default). Any other argument filters the
+//! benchmarks by name.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// A named swap invocation, relative to a fresh fixture directory, and the one undoing it.
+struct Bench {
+    name: &'static str,
+    args: &'static [&'static str],
+    back: &'static [&'static str],
+}
+
+const BENCHES: &[Bench] = &[
+    // The default invocation, without any flag: a location swap of two files across directories.
+    // Every optional feature adds its cost on top of this one.
+    Bench { name: "baseline_no_flags", args: &["a/file1", "b/file2"], back: &["b/file1", "a/file2"] },
+    Bench { name: "name_swap_same_dir", args: &["-n", "a/file1", "a/file3"], back: &["-n", "a/file1", "a/file3"] },
+    // The same swap between two items of the same name, which trade places: a single
+    // `RENAME_EXCHANGE` instead of three renames through a temporary name. The difference with
+    // `baseline_no_flags` is the cost of the rename dance.
+    Bench { name: "exchange_cross_dir", args: &["a/same", "b/same"], back: &["a/same", "b/same"] },
+    Bench { name: "tree_swap", args: &["a/tree1", "b/tree2"], back: &["b/tree1", "a/tree2"] },
+    Bench {
+        name: "verified_tree_swap",
+        args: &["--verify", "a/tree1", "b/tree2"],
+        back: &["--verify", "b/tree1", "a/tree2"],
+    },
+];
+
+fn benches(c: &mut Criterion) {
+    for bench in BENCHES {
+        let fixture = Fixture::create(bench.name);
+        let forth = command_line(&fixture.root, bench.args);
+        let back = command_line(&fixture.root, bench.back);
+
+        let mut backwards = false;
+        c.bench_function(bench.name, |b| {
+            b.iter(|| {
+                let args = if backwards { &back } else { &forth };
+                if let Err(e) = swap::swap(args.iter().cloned()) {
+                    panic!("swap {:?} failed: {}", args, e);
+                }
+                backwards = !backwards;
+            })
+        });
+    }
+}
+
+/// Returns the command line running `args`, with the paths made absolute under `root`.
+fn command_line(root: &Path, args: &[&str]) -> Vec<PathBuf> {
+    let paths = args.iter().map(|arg| if arg.starts_with('-') { PathBuf::from(arg) } else { root.join(arg) });
+    std::iter::once(PathBuf::from("swap")).chain(paths).collect()
+}
+
+/// A scratch directory holding the items the benchmarks swap, removed on drop.
+struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    fn create(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("swap-bench-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+
+        for dir in ["a", "b"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("a/file1"), b"first").unwrap();
+        fs::write(root.join("b/file2"), b"second").unwrap();
+        fs::write(root.join("a/file3"), b"third").unwrap();
+        fs::write(root.join("a/same"), b"fourth").unwrap();
+        fs::write(root.join("b/same"), b"fifth").unwrap();
+
+        // Two small trees, so that the directory case is not just an empty rename.
+        for tree in ["a/tree1", "b/tree2"] {
+            for sub in 0..4 {
+                let dir = root.join(tree).join(format!("sub{}", sub));
+                fs::create_dir_all(&dir).unwrap();
+                for file in 0..8 {
+                    fs::write(dir.join(format!("file{}", file)), vec![b'x'; 4096]).unwrap();
+                }
+            }
+        }
+        Fixture { root }
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+criterion_group!(swaps, benches);
+criterion_main!(swaps);
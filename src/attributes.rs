@@ -0,0 +1,260 @@
+//! `--attributes-only`: exchanges the metadata of two items, leaving their contents and locations alone.
+//!
+//! The attributes are read from both items first, then applied crosswise. If applying them to the
+//! second item fails, the first item gets its own attributes back, so a failed exchange changes nothing.
+
+use std::io;
+use std::path::Path;
+
+use crate::{warn, Attribute, Cli, SwapError};
+
+/// Prefix of the extended attributes that are exchanged. The other namespaces (`security`,
+/// `trusted`, `system`) carry policy rather than user data and are left alone.
+#[cfg(unix)]
+const XATTR_NAMESPACE: &str = "user.";
+
+/// Extended attributes holding the POSIX ACLs of an item: its access ACL, and for a directory
+/// the default ACL inherited by the entries created in it.
+#[cfg(unix)]
+const ACL_XATTRS: &[&str] = &["system.posix_acl_access", "system.posix_acl_default"];
+
+/// The value of each ACL extended attribute of an item, `None` for the ones it does not have.
+#[cfg(unix)]
+type Acls = Vec<(&'static str, Option<Vec<u8>>)>;
+
+/// The permission bits and ownership of an item.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    mode: u32,
+    uid: u32,
+    gid: u32,
+}
+
+impl Permissions {
+    #[cfg(unix)]
+    fn of(metadata: &std::fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Permissions { mode: metadata.mode() & 0o7777, uid: metadata.uid(), gid: metadata.gid() }
+    }
+
+    /// Reads the permissions of `path`, without following a symlink. Returns `None` where
+    /// there are no Unix permissions.
+    pub fn read(path: &Path) -> Result<Option<Self>, SwapError> {
+        let metadata = path.symlink_metadata().map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+        #[cfg(unix)]
+        return Ok(Some(Permissions::of(&metadata)));
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            Ok(None)
+        }
+    }
+
+    /// Describes how `after` differs from these permissions, e.g. `mode changed 0700 -> 0644`.
+    pub fn changes(&self, after: &Permissions) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.mode != after.mode {
+            changes.push(format!("mode changed {:04o} -> {:04o}", self.mode, after.mode));
+        }
+        if (self.uid, self.gid) != (after.uid, after.gid) {
+            changes.push(format!("owner changed {}:{} -> {}:{}", self.uid, self.gid, after.uid, after.gid));
+        }
+        changes
+    }
+}
+
+/// The attributes of an item, as read before the exchange.
+#[cfg(unix)]
+struct Attributes {
+    permissions: Permissions,
+    accessed: std::time::SystemTime,
+    modified: std::time::SystemTime,
+    xattrs: Vec<(String, Vec<u8>)>,
+    /// The ACL extended attributes, or `None` where the filesystem does not support ACLs.
+    acls: Option<Acls>,
+}
+
+/// Exchanges the attributes selected by `--preserve` (all of them by default) between both items.
+pub fn exchange(path1: &Path, path2: &Path, cli: &Cli) -> Result<(), SwapError> {
+    let selected: &[Attribute] = if cli.preserve.is_empty() { Attribute::ALL } else { &cli.preserve };
+    let names: Vec<String> = selected.iter().map(|attribute| attribute.to_string()).collect();
+
+    if cli.dry_run {
+        println!("Would exchange {} between '{}' and '{}'", names.join(", "), path1.display(), path2.display());
+        return Ok(());
+    }
+    log!(cli, "Exchanging {} between '{}' and '{}'...", names.join(", "), path1.display(), path2.display());
+    exchange_selected(path1, path2, selected)
+}
+
+#[cfg(unix)]
+fn exchange_selected(path1: &Path, path2: &Path, selected: &[Attribute]) -> Result<(), SwapError> {
+    let attributes1 = Attributes::read(path1)?;
+    let attributes2 = Attributes::read(path2)?;
+
+    if let Err(cause) = attributes2.apply(path1, selected) {
+        return Err(restore(cause, &[(&attributes1, path1)], selected));
+    }
+    if let Err(cause) = attributes1.apply(path2, selected) {
+        return Err(restore(cause, &[(&attributes1, path1), (&attributes2, path2)], selected));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn exchange_selected(path1: &Path, _path2: &Path, _selected: &[Attribute]) -> Result<(), SwapError> {
+    Err(SwapError::Io(io::Error::from(io::ErrorKind::Unsupported), path1.to_path_buf()))
+}
+
+/// Puts the original attributes back after a failed exchange.
+#[cfg(unix)]
+fn restore(cause: SwapError, originals: &[(&Attributes, &Path)], selected: &[Attribute]) -> SwapError {
+    for (attributes, path) in originals {
+        if let Err(rollback) = attributes.apply(path, selected) {
+            return SwapError::RollbackFailed { cause: Box::new(cause), rollback: Box::new(rollback) };
+        }
+    }
+    cause
+}
+
+#[cfg(unix)]
+impl Attributes {
+    fn read(path: &Path) -> Result<Self, SwapError> {
+        let io_err = |e| SwapError::Io(e, path.to_path_buf());
+        let metadata = path.symlink_metadata().map_err(io_err)?;
+        if metadata.file_type().is_symlink() {
+            return Err(SwapError::SymlinkAttributes(path.to_path_buf()));
+        }
+
+        let names = match crate::sys::list_xattrs(path) {
+            Ok(names) => names,
+            Err(e) if crate::sys::is_unsupported(&e) => Vec::new(),
+            Err(e) => return Err(io_err(e)),
+        };
+        let mut xattrs = Vec::new();
+        for name in names {
+            let Some(name) = name.to_str().filter(|name| name.starts_with(XATTR_NAMESPACE)) else { continue };
+            if let Some(value) = crate::sys::get_xattr(path, name).map_err(io_err)? {
+                xattrs.push((name.to_string(), value));
+            }
+        }
+
+        Ok(Attributes {
+            permissions: Permissions::of(&metadata),
+            accessed: metadata.accessed().map_err(io_err)?,
+            modified: metadata.modified().map_err(io_err)?,
+            xattrs,
+            acls: read_acls(path).map_err(io_err)?,
+        })
+    }
+
+    /// Applies the selected attributes to `path`. Ownership goes first, as changing it may
+    /// clear the set-user-ID bits, and timestamps go last, so nothing else touches them. The
+    /// ownership and mode are only set where they differ, so that restoring an item the
+    /// exchange did not change needs no more rights than reading it.
+    fn apply(&self, path: &Path, selected: &[Attribute]) -> Result<(), SwapError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let io_err = |e| SwapError::Io(e, path.to_path_buf());
+        let current = Permissions::of(&path.symlink_metadata().map_err(io_err)?);
+        let same_owner = (current.uid, current.gid) == (self.permissions.uid, self.permissions.gid);
+        if selected.contains(&Attribute::Ownership) && !same_owner {
+            std::os::unix::fs::chown(path, Some(self.permissions.uid), Some(self.permissions.gid)).map_err(io_err)?;
+        }
+        // A change of owner may have cleared the set-user-ID bits: compare with the mode afterwards.
+        let current = if same_owner { current } else { Permissions::of(&path.symlink_metadata().map_err(io_err)?) };
+        if selected.contains(&Attribute::Mode) && current.mode != self.permissions.mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(self.permissions.mode)).map_err(io_err)?;
+        }
+        if selected.contains(&Attribute::Xattrs) {
+            self.apply_xattrs(path).map_err(io_err)?;
+        }
+        // After the mode: an access ACL also sets the group permission bits.
+        if selected.contains(&Attribute::Acls) {
+            self.apply_acls(path).map_err(io_err)?;
+        }
+        if selected.contains(&Attribute::Timestamps) {
+            // Through the path: opening the item could be refused by its new mode, or block on a FIFO.
+            crate::sys::set_times(path, self.accessed, self.modified).map_err(io_err)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the user extended attributes of `path` with the recorded ones.
+    fn apply_xattrs(&self, path: &Path) -> io::Result<()> {
+        let current = match crate::sys::list_xattrs(path) {
+            Ok(names) => names,
+            Err(e) if crate::sys::is_unsupported(&e) && self.xattrs.is_empty() => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for name in current.iter().filter_map(|name| name.to_str()) {
+            let kept = self.xattrs.iter().any(|(kept, _)| kept == name);
+            if name.starts_with(XATTR_NAMESPACE) && !kept {
+                crate::sys::remove_xattr(path, name)?;
+            }
+        }
+        for (name, value) in &self.xattrs {
+            crate::sys::set_xattr(path, name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the ACLs of `path` with the recorded ones. Items without ACLs, or on a filesystem
+    /// without ACL support, have nothing to apply; ACLs that cannot be applied for lack of support
+    /// are reported with a warning.
+    fn apply_acls(&self, path: &Path) -> io::Result<()> {
+        let Some(acls) = &self.acls else { return Ok(()) };
+        for (name, value) in acls {
+            let applied = match value {
+                Some(value) => crate::sys::set_xattr(path, name, value),
+                None => match crate::sys::remove_xattr(path, name) {
+                    Err(e) if e.raw_os_error() == Some(libc::ENODATA) => Ok(()),
+                    result => result,
+                },
+            };
+            match applied {
+                Err(e) if crate::sys::is_unsupported(&e) && value.is_some() => {
+                    warn(format!("The ACLs could not be applied to '{}', its filesystem does not support them.", path.display()));
+                    return Ok(());
+                }
+                Err(e) if crate::sys::is_unsupported(&e) => return Ok(()),
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the ACL extended attributes of `path`. Returns `None` where ACLs are not supported.
+#[cfg(unix)]
+fn read_acls(path: &Path) -> io::Result<Option<Acls>> {
+    let mut acls = Vec::new();
+    for name in ACL_XATTRS {
+        match crate::sys::get_xattr(path, name) {
+            Ok(value) => acls.push((*name, value)),
+            Err(e) if crate::sys::is_unsupported(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(acls))
+}
+
+/// Copies the POSIX ACLs of `src` to `dst`, for `--preserve-acls`. Returns false when one of
+/// both filesystems does not support ACLs, so they could not all be copied.
+#[cfg(unix)]
+pub fn copy_acls(src: &Path, dst: &Path) -> io::Result<bool> {
+    let Some(acls) = read_acls(src)? else { return Ok(false) };
+    for (name, value) in acls {
+        let Some(value) = value else { continue };
+        match crate::sys::set_xattr(dst, name, &value) {
+            Err(e) if crate::sys::is_unsupported(&e) => return Ok(false),
+            result => result?,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+pub fn copy_acls(_src: &Path, _dst: &Path) -> io::Result<bool> {
+    Ok(false)
+}
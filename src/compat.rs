@@ -0,0 +1,61 @@
+//! `--compat-mv`: reports errors the way GNU coreutils `mv` does, for scripts written around
+//! a pair of `mv` calls.
+//!
+//! Only the failures a pair of `mv` calls could run into have an `mv` message: missing paths,
+//! identical paths, a directory moved into itself, refused replacements and the I/O errors of
+//! the moves. Every other error is specific to `swap` and keeps its message, with the `mv:`
+//! prefix in place of `Error:`. The exit statuses of `swap` already match the ones of `mv`: 1 for
+//! any failure, and 130 for an interruption. Like `mv`, a successful swap prints nothing.
+
+use std::io;
+use std::path::Path;
+
+use crate::{Cli, SwapError};
+
+/// Returns the message `mv` would print for `error`.
+pub fn mv_message(error: &SwapError, cli: &Cli) -> String {
+    let args = cli.path1.as_deref().zip(cli.path2.as_deref());
+    match error {
+        SwapError::PathNotFound(path) => format!("mv: cannot stat {}: No such file or directory", quote(path)),
+        SwapError::SamePath => match args {
+            Some((path1, path2)) => format!("mv: {} and {} are the same file", quote(path1), quote(path2)),
+            None => "mv: the two paths are the same file".to_string(),
+        },
+        SwapError::SwapIntoSubdirectory => match args {
+            Some((path1, path2)) => format!("mv: cannot move {} to a subdirectory of itself, {}", quote(path1), quote(path2)),
+            None => "mv: cannot move a directory to a subdirectory of itself".to_string(),
+        },
+        SwapError::SameInode { path1, path2, .. } => format!("mv: {} and {} are the same file", quote(path1), quote(path2)),
+        SwapError::DestinationExists(path) => format!("mv: not replacing {}", quote(path)),
+        SwapError::Io(err, path) if err.kind() == io::ErrorKind::NotFound => {
+            format!("mv: cannot stat {}: {}", quote(path), strerror(err))
+        }
+        SwapError::Io(err, path) => format!("mv: cannot move {}: {}", quote(path), strerror(err)),
+        // Like two failed `mv` calls, one line per problem.
+        SwapError::PreflightFailed(errors) => errors.iter().map(|error| mv_message(error, cli)).collect::<Vec<_>>().join("\n"),
+        SwapError::RollbackFailed { cause, rollback } => {
+            let rollback = mv_message(rollback, cli);
+            let rollback = rollback.strip_prefix("mv: ").unwrap_or(&rollback);
+            format!("{}\nmv: rolling back also failed, manual recovery is needed: {}", mv_message(cause, cli), rollback)
+        }
+        SwapError::FailedAfterSwap(cause) => format!("{}\nmv: the swap itself was applied and is not rolled back", mv_message(cause, cli)),
+        other => {
+            let message = other.to_string();
+            format!("mv: {}", message.strip_prefix("Error: ").unwrap_or(&message))
+        }
+    }
+}
+
+/// Quotes a path like `mv` does in its messages.
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.display())
+}
+
+/// Returns the system description of `err`, without the `(os error N)` suffix std adds.
+fn strerror(err: &io::Error) -> String {
+    let message = err.to_string();
+    match message.rsplit_once(" (os error ") {
+        Some((description, _)) => description.to_string(),
+        None => message,
+    }
+}
@@ -0,0 +1,533 @@
+//! Copy fallback used when an item has to move to another filesystem, where `rename` fails with `EXDEV`.
+//!
+//! The copy is fully written and synced before the source is removed, so an error or an
+//! interruption at any point leaves the source untouched and no partial destination behind.
+//!
+//! With `--verify`, the source is hashed as it streams through the copy, and only the synced copy
+//! is read back to be compared with it: verifying costs a single extra read of the data.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, FileTimes};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use crate::attributes;
+use crate::hash::{self, Sha256};
+use crate::metrics;
+use crate::sys::{self, FileId, PinnedDir};
+use crate::{check_cancelled, format_size, temporary_name, Cli, SwapError, TempGuard};
+
+/// Size of the buffer used to stream file contents.
+const BUFFER_SIZE: usize = 128 * 1024;
+
+/// Moves the entry `from_name` of `from_dir` to `to_name` in `to_dir`, on another filesystem,
+/// by copying it. `expected` is the identity the source must still have.
+/// Returns the identity of the new entry.
+pub fn move_across(
+    from_dir: &PinnedDir,
+    from_name: &OsStr,
+    to_dir: &PinnedDir,
+    to_name: &OsStr,
+    expected: FileId,
+    cancel: Option<&AtomicBool>,
+    cli: &Cli,
+) -> Result<FileId, SwapError> {
+    let from_path = from_dir.path().join(from_name);
+    let metadata = from_path.symlink_metadata().map_err(|e| SwapError::Io(e, from_path.clone()))?;
+    let mut throttle = cli.bwlimit.map(Throttle::new);
+
+    let moved = if metadata.is_dir() {
+        move_tree(from_dir, from_name, to_dir, to_name, expected, cancel, throttle.as_mut(), cli)
+    } else if metadata.is_file() {
+        move_file(from_dir, from_name, to_dir, to_name, expected, cancel, throttle.as_mut(), cli)
+    } else {
+        // Symlinks and special files are recreated like any entry of a directory tree.
+        let temp_name = temporary_name(to_name);
+        let temp = TempGuard::new(to_dir.path().join(&temp_name));
+        let mut copy = TreeCopy::new(cancel, throttle.as_mut(), false);
+        copy.acls = cli.preserve_acls;
+        copy.copy_entry(&from_path, temp.path())?;
+        sys::rename_at(to_dir, &temp_name, to_dir, to_name).map_err(|e| SwapError::Io(e, temp.path().to_path_buf()))?;
+        temp.commit();
+        from_dir.remove_file(from_name).map_err(|e| SwapError::Io(e, from_path))?;
+        to_dir.entry_id(to_name).map_err(|e| SwapError::Io(e, to_dir.path().join(to_name)))
+    };
+
+    if let (Some(throttle), Ok(_)) = (&throttle, &moved) {
+        log!(cli, "    Copied {} at {}/s (limit {}/s).", format_size(throttle.consumed), format_size(throttle.effective_rate()), format_size(throttle.rate as u64));
+    }
+    moved
+}
+
+/// Moves the directory `from_name` of `from_dir` to `to_name` in `to_dir` by copying the whole tree.
+///
+/// By default the first error aborts the move and removes the partial copy. With `--best-effort`,
+/// entries that cannot be copied are skipped and reported instead: they stay in the source tree,
+/// which is renamed to a `.swap-leftovers.<uuid>` directory next to its original place.
+/// `expected` is the identity the source must still have once copied.
+#[allow(clippy::too_many_arguments)]
+fn move_tree(
+    from_dir: &PinnedDir,
+    from_name: &OsStr,
+    to_dir: &PinnedDir,
+    to_name: &OsStr,
+    expected: FileId,
+    cancel: Option<&AtomicBool>,
+    throttle: Option<&mut Throttle>,
+    cli: &Cli,
+) -> Result<FileId, SwapError> {
+    let from_path = from_dir.path().join(from_name);
+    let to_path = to_dir.path().join(to_name);
+    let temp_name = temporary_name(to_name);
+    let temp = TempGuard::new(to_dir.path().join(&temp_name));
+
+    let mut copy = TreeCopy::new(cancel, throttle, cli.best_effort);
+    copy.verify = cli.verify;
+    copy.acls = cli.preserve_acls;
+    copy.sort_entries = cli.sort_entries;
+    if cli.preserve_hardlinks {
+        copy.links = Some(HashMap::new());
+    }
+    copy.copy_entry(&from_path, temp.path())?;
+    // The source is removed through its path: it must still be the item that was copied, not an
+    // entry put in its place during the copy. Otherwise the copy is dropped and nothing is removed.
+    if from_dir.entry_id(from_name).ok() != Some(expected) {
+        return Err(SwapError::PathChangedUnderneath(from_path));
+    }
+    sys::rename_at(to_dir, &temp_name, to_dir, to_name).map_err(|e| SwapError::Io(e, temp.path().to_path_buf()))?;
+    temp.commit();
+    log!(cli, "    Copied {} entries to '{}' ({} as hard links).", copy.copied.len(), to_path.display(), copy.linked);
+
+    if copy.failures.is_empty() {
+        fs::remove_dir_all(&from_path).map_err(|e| SwapError::Io(e, from_path.clone()))?;
+    } else {
+        // Only remove what made it to the destination: the rest must not be lost.
+        for copied in &copy.copied {
+            let _ = if copied.is_dir() { fs::remove_dir(copied) } else { fs::remove_file(copied) };
+        }
+        let leftovers = leftovers_name(to_name);
+        sys::rename_at(from_dir, from_name, from_dir, &leftovers).map_err(|e| SwapError::Io(e, from_path.clone()))?;
+        report_failures(&copy.failures, &to_path, &from_dir.path().join(&leftovers));
+    }
+
+    to_dir.entry_id(to_name).map_err(|e| SwapError::Io(e, to_path))
+}
+
+/// Returns the name under which the entries `--best-effort` could not copy are kept,
+/// derived from the final name of the item rather than from its temporary one.
+fn leftovers_name(name: &OsStr) -> OsString {
+    let name = name.to_str().unwrap_or("temp");
+    OsString::from(format!("{}.swap-leftovers.{}", name, uuid::Uuid::new_v4()))
+}
+
+/// Prints the entries skipped by `--best-effort`, with the reason for each of them.
+/// Every reason already names the entry it is about.
+fn report_failures(failures: &[(PathBuf, SwapError)], dest: &Path, leftovers: &Path) {
+    crate::warn(format!(
+        "{} entries could not be copied to '{}' and were kept in '{}':",
+        failures.len(),
+        dest.display(),
+        leftovers.display()
+    ));
+    for (_, reason) in failures {
+        eprintln!("  - {}", reason);
+    }
+}
+
+/// Recursive copy of a directory tree, entry by entry.
+struct TreeCopy<'a> {
+    cancel: Option<&'a AtomicBool>,
+    throttle: Option<&'a mut Throttle>,
+    best_effort: bool,
+    /// Source entries copied so far, children before their parent directory.
+    copied: Vec<PathBuf>,
+    /// Entries skipped in best-effort mode, with the reason why.
+    failures: Vec<(PathBuf, SwapError)>,
+    /// With `--preserve-hardlinks`, the copy of every multiply-linked file, by source identity.
+    links: Option<HashMap<FileId, PathBuf>>,
+    /// Entries recreated as a hard link to an earlier copy.
+    linked: usize,
+    /// With `--verify`, read back every copied file and compare it with the source.
+    verify: bool,
+    /// With `--preserve-acls`, copy the ACLs of every entry.
+    acls: bool,
+    /// Set once the lack of ACL support has been reported, so it is reported once per move.
+    acls_unsupported: bool,
+    /// With `--sort-entries`, create the entries of each directory in byte order of their names
+    /// rather than in the order the source lists them.
+    sort_entries: bool,
+}
+
+impl<'a> TreeCopy<'a> {
+    fn new(cancel: Option<&'a AtomicBool>, throttle: Option<&'a mut Throttle>, best_effort: bool) -> Self {
+        TreeCopy { cancel, throttle, best_effort, copied: Vec::new(), failures: Vec::new(), links: None, linked: 0, verify: false, acls: false, acls_unsupported: false, sort_entries: false }
+    }
+
+    /// Copies `src` to `dst`, which must not exist yet.
+    fn copy_entry(&mut self, src: &Path, dst: &Path) -> Result<(), SwapError> {
+        if let Some(cancel) = self.cancel {
+            check_cancelled(cancel)?;
+        }
+        let io_err = |e| SwapError::Io(e, src.to_path_buf());
+        let metadata = src.symlink_metadata().map_err(io_err)?;
+        let file_type = metadata.file_type();
+
+        if file_type.is_dir() {
+            fs::create_dir(dst).map_err(|e| SwapError::Io(e, dst.to_path_buf()))?;
+            let mut names = fs::read_dir(src).map_err(io_err)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(io_err)?;
+            if self.sort_entries {
+                names.sort_unstable();
+            }
+            for name in names {
+                self.copy_child(&src.join(&name), &dst.join(&name))?;
+            }
+            // Restored last, as read-only permissions would prevent creating the children.
+            set_metadata(dst, &metadata)?;
+            self.copy_acls(src, dst)?;
+        } else if file_type.is_file() && self.link_copy(src, dst, &metadata)? {
+            self.linked += 1;
+        } else if file_type.is_file() {
+            let mut source = File::open(src).map_err(io_err)?;
+            let mut dest = File::create_new(dst).map_err(|e| SwapError::Io(e, dst.to_path_buf()))?;
+            let mut hasher = self.verify.then(Sha256::new);
+            copy_contents(&mut source, &mut dest, dst, self.cancel, self.throttle.as_deref_mut(), hasher.as_mut())?;
+            finish(&dest, &metadata, dst)?;
+            self.copy_acls(src, dst)?;
+            if let Some(hasher) = hasher {
+                verify_copy(dst, hasher.finish())?;
+            }
+            if let (Some(links), Ok(id)) = (self.links.as_mut(), FileId::of_metadata(&metadata)) {
+                if link_count(&metadata) > 1 {
+                    links.insert(id, dst.to_path_buf());
+                }
+            }
+        } else if file_type.is_symlink() {
+            copy_symlink(src, dst)?;
+        } else if is_node(&file_type) {
+            // Never read a device: recreate the node itself, with the same major/minor numbers.
+            sys::make_node(dst, &metadata).map_err(|e| SwapError::Io(e, dst.to_path_buf()))?;
+            self.copy_acls(src, dst)?;
+        } else {
+            return Err(SwapError::UnsupportedFileType(src.to_path_buf()));
+        }
+
+        self.copied.push(src.to_path_buf());
+        Ok(())
+    }
+
+    /// Copies the ACLs of `src` to `dst` with `--preserve-acls`.
+    fn copy_acls(&mut self, src: &Path, dst: &Path) -> Result<(), SwapError> {
+        if self.acls && !copy_acls(src, dst, !self.acls_unsupported)? {
+            self.acls_unsupported = true;
+        }
+        Ok(())
+    }
+
+    /// Recreates `dst` as a hard link to the copy of an earlier link to the same inode as `src`.
+    /// Returns false when the file still has to be copied: it was not seen yet, or its first
+    /// copy was removed after a failure in best-effort mode.
+    fn link_copy(&mut self, src: &Path, dst: &Path, metadata: &std::fs::Metadata) -> Result<bool, SwapError> {
+        let Some(links) = &self.links else { return Ok(false) };
+        let Some(first) = FileId::of_metadata(metadata).ok().and_then(|id| links.get(&id)) else { return Ok(false) };
+        match fs::hard_link(first, dst) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && first.symlink_metadata().is_err() => Ok(false),
+            Err(e) => Err(SwapError::Io(e, src.to_path_buf())),
+        }
+    }
+
+    /// Copies a child entry, recording the failure instead of aborting in best-effort mode.
+    /// A partially copied entry is removed, and its children are no longer considered copied.
+    fn copy_child(&mut self, src: &Path, dst: &Path) -> Result<(), SwapError> {
+        let copied_before = self.copied.len();
+        match self.copy_entry(src, dst) {
+            Err(SwapError::Cancelled) => Err(SwapError::Cancelled),
+            Err(e) if self.best_effort => {
+                self.copied.truncate(copied_before);
+                let _ = match dst.symlink_metadata() {
+                    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(dst),
+                    Ok(_) => fs::remove_file(dst),
+                    Err(_) => Ok(()),
+                };
+                self.failures.push((src.to_path_buf(), e));
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+/// Returns true for the special files `mknod` can recreate: block and character devices, and
+/// FIFOs. A socket is only meaningful to the process listening on it, so it is not recreated.
+#[cfg(unix)]
+fn is_node(file_type: &fs::FileType) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    file_type.is_block_device() || file_type.is_char_device() || file_type.is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_node(_file_type: &fs::FileType) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn link_count(metadata: &std::fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::nlink(metadata)
+}
+
+#[cfg(not(unix))]
+fn link_count(_metadata: &std::fs::Metadata) -> u64 {
+    1
+}
+
+/// Recreates the symlink `src` as `dst`, pointing to the same (unresolved) target.
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> Result<(), SwapError> {
+    let target = fs::read_link(src).map_err(|e| SwapError::Io(e, src.to_path_buf()))?;
+    std::os::unix::fs::symlink(target, dst).map_err(|e| SwapError::Io(e, dst.to_path_buf()))
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, _dst: &Path) -> Result<(), SwapError> {
+    Err(SwapError::UnsupportedFileType(src.to_path_buf()))
+}
+
+/// Applies the permissions and timestamps of `metadata` to the directory `dst`.
+fn set_metadata(dst: &Path, metadata: &std::fs::Metadata) -> Result<(), SwapError> {
+    let dir = File::open(dst).map_err(|e| SwapError::Io(e, dst.to_path_buf()))?;
+    finish(&dir, metadata, dst)
+}
+
+/// Moves the regular file `from_name` of `from_dir` to `to_name` in `to_dir` by copying it.
+/// `expected` is the identity the source must still have. Returns the identity of the new file.
+#[allow(clippy::too_many_arguments)]
+fn move_file(
+    from_dir: &PinnedDir,
+    from_name: &OsStr,
+    to_dir: &PinnedDir,
+    to_name: &OsStr,
+    expected: FileId,
+    cancel: Option<&AtomicBool>,
+    mut throttle: Option<&mut Throttle>,
+    cli: &Cli,
+) -> Result<FileId, SwapError> {
+    let from_path = from_dir.path().join(from_name);
+    let to_path = to_dir.path().join(to_name);
+
+    let mut source = from_dir.open_file(from_name).map_err(|e| SwapError::Io(e, from_path.clone()))?;
+    let metadata = source.metadata().map_err(|e| SwapError::Io(e, from_path.clone()))?;
+    if FileId::of_metadata(&metadata).ok() != Some(expected) {
+        return Err(SwapError::PathChangedUnderneath(from_path));
+    }
+
+    let staged_anonymously = cli.concurrency_safe
+        && stage_anonymous(&mut source, &metadata, to_dir, to_name, cancel, throttle.as_deref_mut(), cli)?;
+    if !staged_anonymously {
+        stage_named(&mut source, &metadata, to_dir, to_name, cancel, throttle, cli.verify)?;
+    }
+
+    if cli.preserve_acls {
+        copy_acls(&from_path, &to_path, true)?;
+    }
+
+    from_dir.remove_file(from_name).map_err(|e| SwapError::Io(e, from_path))?;
+    to_dir.entry_id(to_name).map_err(|e| SwapError::Io(e, to_path))
+}
+
+/// Copies the POSIX ACLs of `src` to `dst`. Returns false when a filesystem does not support
+/// them, which is only a warning (printed if `warn` is set): the permission bits were copied.
+fn copy_acls(src: &Path, dst: &Path, warn: bool) -> Result<bool, SwapError> {
+    let copied = attributes::copy_acls(src, dst).map_err(|e| SwapError::Io(e, dst.to_path_buf()))?;
+    if !copied && warn {
+        crate::warn(format!("The ACLs of '{}' could not be preserved, ACLs are not supported on both filesystems.", src.display()));
+    }
+    Ok(copied)
+}
+
+/// Copies the file into an anonymous `O_TMPFILE` inode and links it into place once complete,
+/// so no temporary name ever appears in the destination directory.
+/// Returns `false` when the platform or filesystem cannot do it, or the destination is taken.
+fn stage_anonymous(
+    source: &mut File,
+    metadata: &std::fs::Metadata,
+    to_dir: &PinnedDir,
+    to_name: &OsStr,
+    cancel: Option<&AtomicBool>,
+    throttle: Option<&mut Throttle>,
+    cli: &Cli,
+) -> Result<bool, SwapError> {
+    let to_path = to_dir.path().join(to_name);
+
+    // Linking never replaces an entry, unlike a rename: leave that case to the named staging.
+    if to_dir.entry_id(to_name).is_ok() {
+        log!(cli, "    '{}' already exists, staging under a temporary name instead.", to_path.display());
+        return Ok(false);
+    }
+
+    let mut staged = match to_dir.create_anonymous_file(0o600) {
+        Ok(file) => file,
+        Err(e) => {
+            log!(cli, "    Anonymous staging unavailable ({}), using a temporary name instead.", e);
+            return Ok(false);
+        }
+    };
+
+    let mut hasher = cli.verify.then(Sha256::new);
+    copy_contents(source, &mut staged, &to_path, cancel, throttle, hasher.as_mut())?;
+    finish(&staged, metadata, &to_path)?;
+    // Read back through the descriptor just written, before the copy has a name: a bad one is
+    // freed on close and never shows up at the destination.
+    if let Some(hasher) = hasher {
+        verify_file(&mut staged, &to_path, hasher.finish())?;
+    }
+    to_dir.link_anonymous_file(&staged, to_name).map_err(|e| SwapError::Io(e, to_path.clone()))?;
+    Ok(true)
+}
+
+/// Copies the file under a temporary name next to its destination, then renames it into place.
+/// With `verify`, the copy is checked against the source before the rename.
+fn stage_named(
+    source: &mut File,
+    metadata: &std::fs::Metadata,
+    to_dir: &PinnedDir,
+    to_name: &OsStr,
+    cancel: Option<&AtomicBool>,
+    throttle: Option<&mut Throttle>,
+    verify: bool,
+) -> Result<(), SwapError> {
+    let temp_name = temporary_name(to_name);
+    let temp_path = to_dir.path().join(&temp_name);
+
+    let mut staged = to_dir.create_file(&temp_name, 0o600).map_err(|e| SwapError::Io(e, temp_path.clone()))?;
+    let temp = TempGuard::new(temp_path.clone());
+    let mut hasher = verify.then(Sha256::new);
+    copy_contents(source, &mut staged, &temp_path, cancel, throttle, hasher.as_mut())?;
+    finish(&staged, metadata, &temp_path)?;
+    if let Some(hasher) = hasher {
+        verify_copy(&temp_path, hasher.finish())?;
+    }
+    sys::rename_at(to_dir, &temp_name, to_dir, to_name).map_err(|e| SwapError::Io(e, temp_path))?;
+    temp.commit();
+    Ok(())
+}
+
+/// Streams the contents of `source` into `dest`, checking for cancellation between chunks
+/// and holding each chunk back as long as `--bwlimit` requires. Every chunk read is also fed
+/// to `hasher`, if any.
+fn copy_contents(
+    source: &mut File,
+    dest: &mut File,
+    dest_path: &Path,
+    cancel: Option<&AtomicBool>,
+    mut throttle: Option<&mut Throttle>,
+    mut hasher: Option<&mut Sha256>,
+) -> Result<(), SwapError> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        if let Some(cancel) = cancel {
+            check_cancelled(cancel)?;
+        }
+        let read = match source.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(SwapError::Io(e, dest_path.to_path_buf())),
+        };
+        if let Some(throttle) = throttle.as_deref_mut() {
+            throttle.consume(read as u64);
+        }
+        if let Some(hasher) = hasher.as_deref_mut() {
+            hasher.update(&buffer[..read]);
+        }
+        dest.write_all(&buffer[..read]).map_err(|e| SwapError::Io(e, dest_path.to_path_buf()))?;
+        metrics::add(&metrics::BYTES_COPIED, read as u64);
+    }
+}
+
+/// Reads back the synced copy at `path` and fails with `VerificationFailed` unless its SHA-256
+/// is `expected`, the one of the source computed during the copy.
+fn verify_copy(path: &Path, expected: [u8; 32]) -> Result<(), SwapError> {
+    let mut file = File::open(path).map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+    verify_file(&mut file, path, expected)
+}
+
+/// Like `verify_copy`, through the already open `file`, read from its start. `path` is only
+/// used in errors.
+fn verify_file(file: &mut File, path: &Path, expected: [u8; 32]) -> Result<(), SwapError> {
+    use std::io::Seek;
+
+    let io_err = |e| SwapError::Io(e, path.to_path_buf());
+    file.rewind().map_err(io_err)?;
+    sys::drop_cached_pages(file);
+    let actual = hash::reader_sha256(file).map_err(io_err)?;
+    metrics::add(&metrics::BYTES_VERIFIED, file.metadata().map_err(io_err)?.len());
+
+    if actual != expected {
+        return Err(SwapError::VerificationFailed(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Token bucket enforcing `--bwlimit` over all the files of one move.
+/// It holds a tenth of a second worth of bytes, and at least one chunk.
+pub struct Throttle {
+    /// Allowed rate, in bytes per second.
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+    started: Instant,
+    consumed: u64,
+}
+
+impl Throttle {
+    fn new(rate: u64) -> Self {
+        let now = Instant::now();
+        let rate = rate.max(1) as f64;
+        Throttle { rate, tokens: Self::capacity(rate), refilled: now, started: now, consumed: 0 }
+    }
+
+    fn capacity(rate: f64) -> f64 {
+        (rate / 10.0).max(BUFFER_SIZE as f64)
+    }
+
+    /// Takes `bytes` tokens from the bucket, sleeping until they are available.
+    fn consume(&mut self, bytes: u64) {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.refilled).as_secs_f64() * self.rate).min(Self::capacity(self.rate));
+        self.refilled = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            std::thread::sleep(Duration::from_secs_f64(-self.tokens / self.rate));
+        }
+        self.consumed += bytes;
+    }
+
+    /// Returns the average rate achieved so far, in bytes per second.
+    fn effective_rate(&self) -> u64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 { (self.consumed as f64 / elapsed) as u64 } else { self.consumed }
+    }
+}
+
+/// Applies the source permissions and timestamps to the copy and flushes it to disk.
+fn finish(dest: &File, metadata: &std::fs::Metadata, dest_path: &Path) -> Result<(), SwapError> {
+    let io_err = |e| SwapError::Io(e, dest_path.to_path_buf());
+
+    dest.set_permissions(metadata.permissions()).map_err(io_err)?;
+    let mut times = FileTimes::new();
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    dest.set_times(times).map_err(io_err)?;
+    dest.sync_all().map_err(io_err)
+}
@@ -0,0 +1,511 @@
+//! `--journal`: an intent log making the three-rename dance recoverable after a crash.
+//!
+//! Before the first rename, an `intent` record with every path involved is appended to the
+//! journal and synced to disk. Once the swap completed or was rolled back, a `commit` or `abort`
+//! record for the same id follows. An intent without an outcome is what a crash leaves behind,
+//! and `swap recover-journal` resolves it from the state of the filesystem:
+//!
+//! - the temporary entry is gone: either nothing was renamed yet or the swap completed, and both
+//!   states are consistent;
+//! - the temporary entry exists and the second item is still in place: only the first step was
+//!   done, so the first item is moved back;
+//! - the temporary entry exists and the second item was moved: the swap is completed by moving
+//!   the temporary entry to its final destination, copying it when that is on another filesystem.
+//!
+//! A crash while a record was written leaves a last line cut short or garbled, whose swap had not
+//! started yet: it is ignored with a warning.
+//!
+//! Records are lines, written in the `--journal-format` of the swap: JSON objects by default
+//! (JSON Lines), or tab-separated fields. Each record starts with the version of its format, and
+//! the format of each line is recognized when reading, so a journal can mix both, and old
+//! journals, written in tab-separated fields before versions were added, can still be recovered.
+//! An atomic exchange needs no journal, as it has no intermediate state.
+//!
+//! In JSON, a record is an object with the members `version`, `record` (`intent`, `commit`,
+//! `abort` or `recovered`) and `id`; an intent also has `timestamp`, `path1`, `path2`, `dest1`,
+//! `dest2` and `temp`. Paths are JSON strings, in which each byte that is not valid UTF-8 is
+//! written as an unpaired `\udcXX` escape (the "surrogateescape" convention of Python).
+//! Tab-separated records are `VERSION RECORD ID [TIMESTAMP PATH1 PATH2 DEST1 DEST2 TEMP]`, in
+//! which `\`, tabs and newlines are escaped, and bytes that are not valid UTF-8 are `\xHH`.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{copy, falls_back_to_copy, iso_timestamp, parent_of, sys, warn, Cli, JournalFormat, SwapError};
+
+/// Version of the record formats, bumped on incompatible changes.
+const FORMAT_VERSION: u64 = 1;
+
+/// Names of the paths of an intent record, in order.
+const PATH_FIELDS: [&str; 5] = ["path1", "path2", "dest1", "dest2", "temp"];
+
+/// The journaled paths of a swap done through a temporary entry.
+pub struct Intent<'a> {
+    pub path1: &'a Path,
+    pub path2: &'a Path,
+    pub dest1: &'a Path,
+    pub dest2: &'a Path,
+    pub temp: &'a Path,
+}
+
+/// An intent written to the journal, waiting for its outcome.
+pub struct Entry {
+    journal: PathBuf,
+    format: JournalFormat,
+    id: String,
+}
+
+/// Appends the intent record of a swap and syncs it before anything is renamed.
+pub fn begin(journal: &Path, format: JournalFormat, intent: &Intent) -> Result<Entry, SwapError> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let paths = [intent.path1, intent.path2, intent.dest1, intent.dest2, intent.temp].map(Path::as_os_str);
+    let timestamp = iso_timestamp(std::time::SystemTime::now());
+    append(journal, &encode(format, "intent", &id, Some((&timestamp, paths))))?;
+    Ok(Entry { journal: journal.to_path_buf(), format, id })
+}
+
+impl Entry {
+    /// Records that the swap completed.
+    pub fn commit(self) -> Result<(), SwapError> {
+        append(&self.journal, &encode(self.format, "commit", &self.id, None))
+    }
+
+    /// Records that the swap was rolled back.
+    pub fn abort(self) -> Result<(), SwapError> {
+        append(&self.journal, &encode(self.format, "abort", &self.id, None))
+    }
+}
+
+/// Formats a record: an outcome, or an intent with its timestamp and paths.
+fn encode(format: JournalFormat, record: &str, id: &str, intent: Option<(&str, [&OsStr; 5])>) -> String {
+    match format {
+        JournalFormat::Jsonl => {
+            let mut line = format!("{{\"version\":{},\"record\":\"{}\",\"id\":\"{}\"", FORMAT_VERSION, record, id);
+            if let Some((timestamp, paths)) = intent {
+                line += &format!(",\"timestamp\":\"{}\"", timestamp);
+                for (name, path) in PATH_FIELDS.iter().zip(paths) {
+                    line += &format!(",\"{}\":{}", name, json_string(path));
+                }
+            }
+            line.push('}');
+            line
+        }
+        JournalFormat::Tsv => {
+            let mut line = format!("{}\t{}\t{}", FORMAT_VERSION, record, id);
+            if let Some((timestamp, paths)) = intent {
+                line += &format!("\t{}", timestamp);
+                for path in paths {
+                    line += &format!("\t{}", escape(path));
+                }
+            }
+            line
+        }
+    }
+}
+
+/// Appends one record and syncs the journal, and its directory when the journal is created.
+fn append(journal: &Path, record: &str) -> Result<(), SwapError> {
+    let io_err = |e| SwapError::Io(e, journal.to_path_buf());
+    let created = journal.symlink_metadata().is_err();
+
+    let mut file = OpenOptions::new().create(true).append(true).open(journal).map_err(io_err)?;
+    file.write_all(format!("{}\n", record).as_bytes()).map_err(io_err)?;
+    file.sync_all().map_err(io_err)?;
+
+    if created {
+        let dir = journal.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        File::open(dir).and_then(|dir| dir.sync_all()).map_err(|e| SwapError::Io(e, dir.to_path_buf()))?;
+    }
+    Ok(())
+}
+
+/// An intent read back from the journal.
+struct PendingIntent {
+    format: JournalFormat,
+    id: String,
+    timestamp: String,
+    path1: PathBuf,
+    path2: PathBuf,
+    dest1: PathBuf,
+    temp: PathBuf,
+}
+
+/// Resolves every intent of `journal` that has no outcome, and records how it was resolved.
+pub fn recover(journal: &Path, cli: &Cli) -> Result<(), SwapError> {
+    let pending = read_pending(journal)?;
+    if pending.is_empty() {
+        println!("No interrupted swap in '{}'.", journal.display());
+        return Ok(());
+    }
+
+    let mut unresolved = 0;
+    for intent in pending {
+        match resolve(&intent, cli) {
+            Ok(outcome) => {
+                println!("{} (swap started at {}).", outcome, intent.timestamp);
+                append(journal, &encode(intent.format, "recovered", &intent.id, None))?;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                unresolved += 1;
+            }
+        }
+    }
+
+    if unresolved > 0 {
+        return Err(SwapError::RecoveryIncomplete { journal: journal.to_path_buf(), unresolved });
+    }
+    Ok(())
+}
+
+/// Brings an interrupted swap back to a consistent state, and describes what was done.
+fn resolve(intent: &PendingIntent, cli: &Cli) -> Result<String, SwapError> {
+    let exists = |path: &Path| path.symlink_metadata().is_ok();
+    if !exists(&intent.temp) {
+        return Ok(format!("'{}' and '{}' are consistent, nothing to do", intent.path1.display(), intent.path2.display()));
+    }
+
+    // The second item only leaves its place at the second step, and the temporary entry only
+    // disappears at the third one.
+    let (target, outcome) = if exists(&intent.path2) {
+        (&intent.path1, "Rolled back")
+    } else {
+        (&intent.dest1, "Completed")
+    };
+    if exists(target) {
+        return Err(SwapError::DestinationExists(target.clone()));
+    }
+    move_entry(&intent.temp, target, cli)?;
+    Ok(format!("{} the swap of '{}' and '{}': moved '{}' to '{}'",
+        outcome, intent.path1.display(), intent.path2.display(), intent.temp.display(), target.display()))
+}
+
+/// Renames `from` to `to`, or copies it when `to` is on another filesystem, as the swap did.
+fn move_entry(from: &Path, to: &Path, cli: &Cli) -> Result<(), SwapError> {
+    let (Some(from_name), Some(to_name)) = (from.file_name(), to.file_name()) else {
+        return Err(SwapError::MissingParent(from.to_path_buf()));
+    };
+    let open = |path: &Path| {
+        let parent = parent_of(path)?;
+        sys::PinnedDir::open(parent).map_err(|e| SwapError::Io(e, parent.to_path_buf()))
+    };
+    let (from_dir, to_dir) = (open(from)?, open(to)?);
+    match sys::rename_at(&from_dir, from_name, &to_dir, to_name) {
+        Ok(()) => Ok(()),
+        Err(e) if falls_back_to_copy(&e, &from_dir, &to_dir, to, cli) => {
+            let id = sys::FileId::of_path(from).map_err(|e| SwapError::Io(e, from.to_path_buf()))?;
+            copy::move_across(&from_dir, from_name, &to_dir, to_name, id, None, cli).map(|_| ())
+        }
+        Err(e) => Err(SwapError::Io(e, from.to_path_buf())),
+    }
+}
+
+/// A record read back from the journal.
+enum Record {
+    Intent(PendingIntent),
+    /// The outcome of the intent with this id.
+    Outcome(String),
+}
+
+/// Why a line could not be read.
+enum LineError {
+    Invalid,
+    /// The record was written by a newer `swap`, in this version of the format.
+    Version(u64),
+}
+
+/// Returns the intents of the journal without a later outcome, in order.
+fn read_pending(journal: &Path) -> Result<Vec<PendingIntent>, SwapError> {
+    // Read as bytes: a crash may have cut a record in the middle of a character.
+    let content = fs::read(journal).map_err(|e| SwapError::Io(e, journal.to_path_buf()))?;
+    let mut lines: Vec<&[u8]> = content.split(|&byte| byte == b'\n').collect();
+    if lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+
+    let mut pending: Vec<PendingIntent> = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let record = match std::str::from_utf8(line) {
+            Ok(line) if line.starts_with('{') => parse_json(line),
+            Ok(line) => parse_tsv(line),
+            Err(_) => Err(LineError::Invalid),
+        };
+        match record {
+            Ok(Record::Intent(intent)) => pending.push(intent),
+            Ok(Record::Outcome(id)) => pending.retain(|intent| intent.id != id),
+            // A record cut short or garbled by a crash while it was written never made it to the
+            // disk as a whole, so its swap had not started yet.
+            Err(LineError::Invalid) if index + 1 == lines.len() => {
+                warn(format!("ignoring line {} of the journal '{}', left incomplete by a crash", index + 1, journal.display()));
+            }
+            Err(LineError::Version(version)) => {
+                return Err(SwapError::JournalVersionUnsupported { path: journal.to_path_buf(), line: index + 1, version });
+            }
+            Err(LineError::Invalid) => return Err(SwapError::InvalidJournal { path: journal.to_path_buf(), line: index + 1 }),
+        }
+    }
+    Ok(pending)
+}
+
+/// Reads a tab-separated record. Records without a leading version predate versions and are
+/// read as version 1, which has the same fields.
+fn parse_tsv(line: &str) -> Result<Record, LineError> {
+    let mut fields: Vec<&str> = line.split('\t').collect();
+    if let Some(version) = fields.first().and_then(|field| field.parse::<u64>().ok()) {
+        if version > FORMAT_VERSION {
+            return Err(LineError::Version(version));
+        }
+        fields.remove(0);
+    }
+    match fields.as_slice() {
+        ["intent", id, timestamp, path1, path2, dest1, _dest2, temp] => {
+            let path = |field: &str| unescape(field).ok_or(LineError::Invalid);
+            Ok(Record::Intent(PendingIntent {
+                format: JournalFormat::Tsv,
+                id: id.to_string(),
+                timestamp: timestamp.to_string(),
+                path1: path(path1)?,
+                path2: path(path2)?,
+                dest1: path(dest1)?,
+                temp: path(temp)?,
+            }))
+        }
+        ["commit" | "abort" | "recovered", id] => Ok(Record::Outcome(id.to_string())),
+        _ => Err(LineError::Invalid),
+    }
+}
+
+/// Reads a JSON record.
+fn parse_json(line: &str) -> Result<Record, LineError> {
+    let members = JsonReader { bytes: line.as_bytes(), pos: 0 }.object().ok_or(LineError::Invalid)?;
+    let member = |name: &str| members.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+    let text = |name: &str| match member(name) {
+        Some(JsonValue::String(bytes)) => String::from_utf8(bytes.clone()).map_err(|_| LineError::Invalid),
+        _ => Err(LineError::Invalid),
+    };
+    let path = |name: &str| match member(name) {
+        Some(JsonValue::String(bytes)) => bytes_to_os_string(bytes.clone()).map(PathBuf::from).ok_or(LineError::Invalid),
+        _ => Err(LineError::Invalid),
+    };
+
+    match member("version") {
+        Some(JsonValue::Number(version)) if *version > FORMAT_VERSION => return Err(LineError::Version(*version)),
+        Some(JsonValue::Number(_)) => {}
+        _ => return Err(LineError::Invalid),
+    }
+    match text("record")?.as_str() {
+        "intent" => Ok(Record::Intent(PendingIntent {
+            format: JournalFormat::Jsonl,
+            id: text("id")?,
+            timestamp: text("timestamp")?,
+            path1: path("path1")?,
+            path2: path("path2")?,
+            dest1: path("dest1")?,
+            temp: path("temp")?,
+        })),
+        "commit" | "abort" | "recovered" => Ok(Record::Outcome(text("id")?)),
+        _ => Err(LineError::Invalid),
+    }
+}
+
+/// A value of a JSON record: journals only hold strings, as raw bytes, and integers.
+enum JsonValue {
+    String(Vec<u8>),
+    Number(u64),
+}
+
+/// Reads a JSON object whose members are strings or non-negative integers, which is all that
+/// records hold.
+struct JsonReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonReader<'_> {
+    fn object(mut self) -> Option<Vec<(String, JsonValue)>> {
+        let mut members = Vec::new();
+        self.expect(b'{')?;
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+        } else {
+            loop {
+                let key = String::from_utf8(self.string()?).ok()?;
+                self.expect(b':')?;
+                let value = match self.peek()? {
+                    b'"' => JsonValue::String(self.string()?),
+                    _ => JsonValue::Number(self.number()?),
+                };
+                members.push((key, value));
+                match self.next()? {
+                    b',' => continue,
+                    b'}' => break,
+                    _ => return None,
+                }
+            }
+        }
+        // Nothing may follow the object but whitespace.
+        self.peek().is_none().then_some(members)
+    }
+
+    /// Skips whitespace and returns the next byte without consuming it.
+    fn peek(&mut self) -> Option<u8> {
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn expect(&mut self, expected: u8) -> Option<()> {
+        (self.next()? == expected).then_some(())
+    }
+
+    fn number(&mut self) -> Option<u64> {
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos]).ok()?.parse().ok()
+    }
+
+    /// Reads a string as bytes. An unpaired `\udc80` to `\udcff` escape is the byte `0x80` to `0xff`.
+    fn string(&mut self) -> Option<Vec<u8>> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self.bytes.get(self.pos)?;
+            self.pos += 1;
+            match byte {
+                b'"' => return Some(bytes),
+                b'\\' => {
+                    let escaped = *self.bytes.get(self.pos)?;
+                    self.pos += 1;
+                    match escaped {
+                        b'"' | b'\\' | b'/' => bytes.push(escaped),
+                        b'b' => bytes.push(0x08),
+                        b'f' => bytes.push(0x0c),
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'u' => self.unicode_escape(&mut bytes)?,
+                        _ => return None,
+                    }
+                }
+                byte => bytes.push(byte),
+            }
+        }
+    }
+
+    /// Reads the digits of a `\u` escape, and of the low surrogate following a high one.
+    fn unicode_escape(&mut self, bytes: &mut Vec<u8>) -> Option<()> {
+        let code = self.hex4()?;
+        let c = match code {
+            0xdc80..=0xdcff => {
+                bytes.push((code - 0xdc00) as u8);
+                return Some(());
+            }
+            0xd800..=0xdbff => {
+                let low = self.bytes.get(self.pos..self.pos + 2).filter(|prefix| *prefix == b"\\u").and_then(|_| {
+                    self.pos += 2;
+                    self.hex4()
+                });
+                let low = low.filter(|low| (0xdc00..=0xdfff).contains(low))?;
+                char::from_u32(0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00))?
+            }
+            code => char::from_u32(code)?,
+        };
+        let mut buf = [0; 4];
+        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        Some(())
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4)?).ok()?;
+        self.pos += 4;
+        u32::from_str_radix(digits, 16).ok()
+    }
+}
+
+/// Writes a path as a JSON string, each byte that is not valid UTF-8 as `\udcXX`.
+fn json_string(path: &OsStr) -> String {
+    let mut json = String::from("\"");
+    for chunk in path.as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => json.push_str("\\\""),
+                '\\' => json.push_str("\\\\"),
+                '\n' => json.push_str("\\n"),
+                '\r' => json.push_str("\\r"),
+                '\t' => json.push_str("\\t"),
+                c if u32::from(c) < 0x20 => json.push_str(&format!("\\u{:04x}", u32::from(c))),
+                c => json.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            json.push_str(&format!("\\udc{:02x}", byte));
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Escapes `\`, tabs and newlines, and the bytes that are not valid UTF-8 as `\xHH`.
+fn escape(path: &OsStr) -> String {
+    let mut escaped = String::new();
+    for chunk in path.as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+/// Reverses `escape`. Returns `None` for an invalid escape sequence.
+fn unescape(field: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            '\\' => bytes.push(b'\\'),
+            't' => bytes.push(b'\t'),
+            'n' => bytes.push(b'\n'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+    Some(PathBuf::from(bytes_to_os_string(bytes)?))
+}
+
+#[cfg(unix)]
+fn bytes_to_os_string(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_os_string(bytes: Vec<u8>) -> Option<OsString> {
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
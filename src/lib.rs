@@ -0,0 +1,2711 @@
+//! `swap`, a CLI tool to swap two files or directories. The binary only calls [`main`]; [`swap`]
+//! runs a swap in-process, for the benchmarks in `benches/swap.rs`.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use clap::{Parser, Subcommand, ValueEnum};
+
+/// Macro rule to handle proper logging in case the verbose argument was passed (and not `--summary`).
+/// Defined before the module declarations so that the modules can use it as well.
+macro_rules! log {
+    ($cli:expr, $($arg:tt)*) => {
+        if $cli.verbose && !$cli.summary {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod attributes;
+mod compat;
+mod copy;
+mod hash;
+mod journal;
+mod lock;
+mod manifest;
+mod marker;
+mod metrics;
+mod mmv;
+mod plan;
+mod relink;
+mod risk;
+mod rotate;
+mod sys;
+mod systemd;
+mod tree;
+mod watch;
+mod windows;
+
+// --- Custom Error Type for Clear and Specific Error Handling ---
+
+/// Represents all possible errors that can occur in the application.
+/// Using a custom error enum makes error handling explicit and robust.
+#[derive(Debug)]
+pub enum SwapError {
+    /// An I/O error occurred, wrapping the standard `std::io::Error`.
+    /// We also store the path that caused the error for better context.
+    Io(std::io::Error, PathBuf),
+    /// The specified path does not exist on the filesystem.
+    PathNotFound(PathBuf),
+    /// The user tried to swap a path with itself.
+    SamePath,
+    /// Two different names lead to the same entry of the same directory, spelled differently on a
+    /// case- or normalization-insensitive filesystem.
+    SameInode { path1: PathBuf, path2: PathBuf },
+    /// Both paths are hard links to the same file in the same directory: swapping them is a valid
+    /// rename that changes nothing, reported as a no-op rather than a failure.
+    HardLinksNoOp { path1: PathBuf, path2: PathBuf },
+    /// A critical safety check failed: attempting to swap a directory with one of its own children.
+    /// This would lead to data loss or an invalid filesystem state.
+    SwapIntoSubdirectory,
+    /// Failed to get the parent directory of a path. Should not happen with canonicalized paths.
+    MissingParent(PathBuf),
+    /// Name normalization produced a destination that is already taken, either by an
+    /// unrelated path or by the other item of the swap.
+    NormalizationCollision(PathBuf),
+    /// Completing the swap would leave less free space than `--reserve` on a filesystem.
+    WouldExceedReserve { fs: PathBuf, remaining: u64 },
+    /// The swap was cancelled (e.g. with Ctrl-C) before it completed. Completed steps were rolled back.
+    Cancelled,
+    /// The swap failed and undoing the completed steps failed too. Manual recovery is needed.
+    RollbackFailed { cause: Box<SwapError>, rollback: Box<SwapError> },
+    /// A destination would be created through a symlinked directory (refused under `--no-dereference` or `--strict`).
+    DestinationThroughSymlink(PathBuf),
+    /// The entry is neither a file, a directory nor a symlink, and cannot be copied.
+    UnsupportedFileType(PathBuf),
+    /// `--attributes-only` was given a symlink, whose attributes it does not exchange.
+    SymlinkAttributes(PathBuf),
+    /// `swap history` found no swap recorded on the path.
+    NoSwapHistory(PathBuf),
+    /// An entry was replaced by a different inode between validation and the rename acting on it.
+    PathChangedUnderneath(PathBuf),
+    /// Another process deleted an item during the swap: its pinned inode has no name left.
+    PathUnlinkedDuringSwap(PathBuf),
+    /// A path is not of the type required by `--require-same-type` or `--require-type`.
+    TypeMismatch { path: PathBuf, expected: FileKind, found: FileKind },
+    /// A path is not on the filesystem type required by `--require-fstype`.
+    UnexpectedFsType { path: PathBuf, found: String },
+    /// A `--pair-syntax` argument does not hold exactly two paths.
+    InvalidPairSyntax { arg: OsString, reason: String },
+    /// Recovering from the journal would overwrite an existing entry.
+    DestinationExists(PathBuf),
+    /// A line of a `--journal` file could not be parsed.
+    InvalidJournal { path: PathBuf, line: usize },
+    /// A line of a `--journal` file was written by a newer version of `swap`.
+    JournalVersionUnsupported { path: PathBuf, line: usize, version: u64 },
+    /// `swap recover-journal` could not resolve some interrupted swaps.
+    RecoveryIncomplete { journal: PathBuf, unresolved: usize },
+    /// No file under the `--hash-root` has a SHA-256 starting with the `--by-hash` prefix.
+    HashNotFound { prefix: String, root: PathBuf },
+    /// Several files under the `--hash-root` have a SHA-256 starting with the `--by-hash` prefix.
+    HashNotUnique { prefix: String, matches: Vec<PathBuf> },
+    /// A line of a `--from-file` manifest could not be parsed.
+    InvalidManifest { path: PathBuf, line: usize, reason: String },
+    /// The swap reaches `--risk-threshold` and there is no terminal to confirm it on (see `--yes`).
+    ConfirmationRequired(Vec<String>),
+    /// The user declined to go on with a risky swap.
+    NotConfirmed,
+    /// `--chown` asks for an ownership the process is not privileged to give.
+    ChownNotPermitted(String),
+    /// `--expand` found a variable that is not set in a path argument.
+    UndefinedVariable { arg: OsString, name: String },
+    /// Another swap held the `--lock-dir` lock for too long.
+    LockBusy(PathBuf),
+    /// Another process created the temporary path of the swap before the first rename.
+    TempRaceDetected(PathBuf),
+    /// A rotation would put two items at the same destination.
+    RotationCollision(PathBuf),
+    /// A location rotation would move an item to another filesystem, which takes a copy.
+    RotationAcrossFilesystems(PathBuf),
+    /// `--smoke-test` could not read an item back after the swap.
+    SmokeTestFailed(PathBuf),
+    /// `--verify` found an item whose contents differ from what was there before the swap.
+    VerificationFailed(PathBuf),
+    /// `--report-permissions-diff` under `--strict` found an item whose mode or owner changed.
+    PermissionsChanged { path: PathBuf, changes: Vec<String> },
+    /// A step run once both items are in place (`--smoke-test`, `--verify` of renamed items,
+    /// `--chown`, `--report-permissions-diff --strict`, `--relativize-targets`) failed. The swap
+    /// itself stays applied: nothing is rolled back.
+    FailedAfterSwap(Box<SwapError>),
+    /// The directory holding an item is not writable, so the item cannot be renamed out of it.
+    ParentNotWritable(PathBuf),
+    /// The preflight checks found several problems, all reported at once.
+    PreflightFailed(Vec<SwapError>),
+    /// `--report-conflicts` found existing entries at the destinations of a pair.
+    DestinationsTaken(Vec<PathBuf>),
+    /// `--report-conflicts` found pairs that cannot be swapped as planned.
+    ConflictsFound { conflicting: usize, pairs: usize },
+    /// `--atomic-only` refused a swap that would go through intermediate states.
+    NotAtomic(String),
+    /// The transient unit of `--via-systemd-run=run` failed, with this exit status if it exited.
+    SystemdRunFailed(Option<i32>),
+    /// `swap -` did not find exactly two paths on stdin.
+    InvalidStdinPaths(String),
+    /// The items hold more entries than `--max-entries` allows. Counting stops past the limit,
+    /// so `count` is a lower bound.
+    TooManyEntries { count: u64, limit: u64 },
+    /// An item is on a FUSE filesystem, whose renames may be neither atomic nor complete
+    /// (refused under `--strict`).
+    FuseUnreliable(PathBuf),
+    /// A final name cannot exist on the filesystem of its directory, which follows the Windows
+    /// naming rules (or on any filesystem with `--windows-names always`).
+    InvalidNameForFilesystem(PathBuf),
+    /// `--emit-mmv` cannot write this path in the input of `mmv`, for the given reason.
+    NotMmvExpressible(PathBuf, &'static str),
+    /// The lock file of `--require-lock` does not exist: the orchestrator has not sequenced this step.
+    MissingDeployLock(PathBuf),
+}
+
+// Implement the Display trait to show user-friendly error messages.
+impl fmt::Display for SwapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwapError::Io(err, path) => {
+                write!(f, "I/O error for path '{}': {}", path.display(), err)
+            }
+            SwapError::PathNotFound(path) => {
+                write!(f, "Error: Path not found: '{}'", path.display())
+            }
+            SwapError::SamePath => {
+                write!(f, "Error: The two paths are identical. Nothing to swap.")
+            }
+            SwapError::HardLinksNoOp { path1, path2 } => write!(
+                f,
+                "Error: '{}' and '{}' are hard links to the same file in the same directory, swapping them changes nothing.",
+                path1.display(),
+                path2.display()
+            ),
+            SwapError::SameInode { path1, path2 } => {
+                write!(f, "Error: '{}' and '{}' are the same file (same device and inode). Nothing to swap.", path1.display(), path2.display())?;
+                let names = (path1.file_name().and_then(OsStr::to_str), path2.file_name().and_then(OsStr::to_str));
+                match names {
+                    (Some(name1), Some(name2)) if name1.eq_ignore_ascii_case(name2) => {
+                        write!(f, "\nNote: The names only differ by case, which this filesystem ignores.")
+                    }
+                    (Some(name1), Some(name2)) if !name1.is_ascii() || !name2.is_ascii() => write!(
+                        f,
+                        "\nNote: The names are probably the same text in two Unicode normalizations (composed and decomposed \
+                         accents, NFC and NFD), which this filesystem treats as one name, as APFS and HFS+ do."
+                    ),
+                    _ => Ok(()),
+                }
+            }
+            SwapError::SwapIntoSubdirectory => {
+                write!(f, "Error: Cannot swap a directory with its own subdirectory. This is a safety prevention.")
+            }
+            SwapError::MissingParent(path) => {
+                write!(f, "Error: Could not determine the parent directory of '{}'.", path.display())
+            }
+            SwapError::NormalizationCollision(path) => {
+                write!(f, "Error: Normalized name collides with an existing destination: '{}'.", path.display())
+            }
+            SwapError::Cancelled => {
+                write!(f, "Error: The swap was cancelled. Completed steps were rolled back.")
+            }
+            SwapError::RollbackFailed { cause, rollback } => {
+                write!(f, "{}\nError: Rolling back also failed, manual recovery is needed: {}", cause, rollback)
+            }
+            SwapError::DestinationThroughSymlink(path) => {
+                write!(f, "Error: The destination '{}' would be reached through a symlinked directory. Refusing to continue.", path.display())
+            }
+            SwapError::UnsupportedFileType(path) => {
+                write!(f, "Error: Cannot copy '{}': unsupported file type.", path.display())
+            }
+            SwapError::SymlinkAttributes(path) => write!(
+                f,
+                "Error: '{}' is a symlink. --attributes-only does not exchange the attributes of symlinks: their mode cannot be changed on Linux, and they cannot hold user extended attributes.",
+                path.display()
+            ),
+            SwapError::NoSwapHistory(path) => {
+                write!(f, "Error: No swap recorded on '{}'.", path.display())
+            }
+            SwapError::PathChangedUnderneath(path) => {
+                write!(f, "Error: '{}' was replaced by another file during the swap. Aborting for safety.", path.display())
+            }
+            SwapError::PathUnlinkedDuringSwap(path) => {
+                write!(f, "Error: '{}' was deleted by another process during the swap. Aborting, the other item is back in its place.", path.display())
+            }
+            SwapError::TypeMismatch { path, expected, found } => {
+                write!(f, "Error: '{}' is a {}, expected a {}.", path.display(), found, expected)
+            }
+            SwapError::UnexpectedFsType { path, found } => {
+                write!(f, "Error: '{}' is on {}, not on the filesystem type required by --require-fstype.", path.display(), found)
+            }
+            SwapError::InvalidPairSyntax { arg, reason } => {
+                write!(f, "Error: '{}' is not a valid PATH1:PATH2 pair: {}.", arg.to_string_lossy(), reason)
+            }
+            SwapError::DestinationExists(path) => {
+                write!(f, "Error: '{}' already exists, refusing to overwrite it.", path.display())
+            }
+            SwapError::InvalidJournal { path, line } => {
+                write!(f, "Error: Invalid journal '{}', line {}.", path.display(), line)
+            }
+            SwapError::JournalVersionUnsupported { path, line, version } => {
+                write!(f, "Error: Line {} of the journal '{}' is in version {} of the format, written by a newer swap. Recover it with that version.", line, path.display(), version)
+            }
+            SwapError::RecoveryIncomplete { journal, unresolved } => {
+                write!(f, "Error: {} interrupted swaps of '{}' need manual recovery.", unresolved, journal.display())
+            }
+            SwapError::HashNotFound { prefix, root } => {
+                write!(f, "Error: No file under '{}' has a SHA-256 starting with '{}'.", root.display(), prefix)
+            }
+            SwapError::HashNotUnique { prefix, matches } => {
+                write!(f, "Error: {} files have a SHA-256 starting with '{}', use a longer prefix:", matches.len(), prefix)?;
+                for path in matches {
+                    write!(f, "\n  - {}", path.display())?;
+                }
+                Ok(())
+            }
+            SwapError::InvalidManifest { path, line, reason } => {
+                write!(f, "Error: Invalid manifest '{}', line {}: {}.", path.display(), line, reason)
+            }
+            SwapError::ConfirmationRequired(reasons) => {
+                write!(f, "Error: This swap needs confirmation ({}). Run it from a terminal, or pass --yes.", reasons.join("; "))
+            }
+            SwapError::NotConfirmed => {
+                write!(f, "Error: The swap was not confirmed. Nothing was changed.")
+            }
+            SwapError::UndefinedVariable { arg, name } => {
+                write!(f, "Error: The environment variable '{}' used in '{}' is not set.", name, arg.to_string_lossy())
+            }
+            SwapError::LockBusy(path) => {
+                write!(f, "Error: Timed out waiting for the lock '{}'. If no swap is running, remove it with `swap recover-locks`.", path.display())
+            }
+            SwapError::TempRaceDetected(path) => {
+                write!(f, "Error: Another process keeps creating the temporary path of the swap (last: '{}'). Nothing was changed.", path.display())
+            }
+            SwapError::RotationCollision(path) => {
+                write!(f, "Error: The rotation would move two items to '{}'.", path.display())
+            }
+            SwapError::RotationAcrossFilesystems(path) => {
+                write!(f, "Error: The rotation would move '{}' to another filesystem; rotations only rename, they do not copy. Nothing was changed.", path.display())
+            }
+            SwapError::SmokeTestFailed(path) => {
+                write!(f, "Error: '{}' could not be read back after the swap (--smoke-test).", path.display())
+            }
+            SwapError::PermissionsChanged { path, changes } => {
+                write!(f, "Error: On '{}', {}.", path.display(), changes.join(" and "))
+            }
+            SwapError::FailedAfterSwap(cause) => {
+                write!(f, "{}\nThe swap itself was applied and is not rolled back: both items are at their new locations.", cause)
+            }
+            SwapError::VerificationFailed(path) => {
+                write!(f, "Error: The contents of '{}' differ from the original item (--verify).", path.display())
+            }
+            SwapError::DestinationsTaken(dests) => {
+                let dests: Vec<String> = dests.iter().map(|dest| format!("'{}'", dest.display())).collect();
+                write!(f, "Error: {} already exists.", dests.join(" and "))
+            }
+            SwapError::ConflictsFound { conflicting, pairs } => {
+                write!(f, "Error: {} of {} pairs have conflicts.", conflicting, pairs)
+            }
+            SwapError::NotAtomic(reason) => {
+                write!(f, "Error: The swap cannot be done atomically: {}. Refusing because of --atomic-only, nothing was changed.", reason)
+            }
+            SwapError::SystemdRunFailed(Some(code)) => {
+                write!(f, "Error: systemd-run failed with exit status {}, see its output above.", code)
+            }
+            SwapError::SystemdRunFailed(None) => {
+                write!(f, "Error: systemd-run was killed by a signal.")
+            }
+            SwapError::InvalidStdinPaths(reason) => {
+                write!(f, "Error: `swap -` needs exactly two paths on stdin: {}.", reason)
+            }
+            SwapError::NotMmvExpressible(path, reason) => {
+                write!(f, "Error: Cannot write '{}' for mmv: it {}.", path.display(), reason)
+            }
+            SwapError::MissingDeployLock(path) => {
+                write!(f, "Error: The deploy lock '{}' does not exist, this swap has not been sequenced yet. Nothing was changed.", path.display())
+            }
+            SwapError::InvalidNameForFilesystem(path) => {
+                let reason = path.file_name().and_then(windows::problem).unwrap_or("it breaks the Windows naming rules");
+                write!(f, "Error: '{}' is not a valid name on Windows and SMB storage: {}. Nothing was changed.", path.display(), reason)
+            }
+            SwapError::FuseUnreliable(path) => {
+                write!(f, "Error: '{}' is on a FUSE filesystem, where renames may not be atomic. Refusing because of --strict.", path.display())
+            }
+            SwapError::TooManyEntries { count, limit } => {
+                write!(f, "Error: The swap involves at least {} entries, more than the limit of {} set by --max-entries. Nothing was changed.", count, limit)
+            }
+            SwapError::ParentNotWritable(dir) => {
+                write!(f, "Error: The directory '{}' is not writable, the items in it cannot be renamed.", dir.display())
+            }
+            SwapError::PreflightFailed(errors) => {
+                write!(f, "Error: The swap cannot start, {} problems were found:", errors.len())?;
+                for error in errors {
+                    let message = error.to_string();
+                    write!(f, "\n  - {}", message.strip_prefix("Error: ").unwrap_or(&message))?;
+                }
+                Ok(())
+            }
+            SwapError::ChownNotPermitted(owner) => {
+                write!(f, "Error: Not permitted to change the ownership to '{}'. This needs root privileges.", owner)
+            }
+            SwapError::WouldExceedReserve { fs, remaining } => {
+                write!(f, "Error: The swap would leave only {} free on the filesystem of '{}', below the reserved margin.", format_size(*remaining), fs.display())
+            }
+        }
+    }
+}
+
+// Implement the Error trait to be compatible with Rust's error handling mechanisms.
+impl Error for SwapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SwapError::Io(err, _) => Some(err),
+            SwapError::FailedAfterSwap(cause) => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+
+// --- Command-Line Argument Parsing using `clap` ---
+
+/// A robust CLI tool to swap two files or directories on Linux.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The first path to swap.
+    #[arg(required_unless_present = "from_file")]
+    path1: Option<PathBuf>,
+
+    /// The second path to swap. Both paths are read from stdin if the first one is `-`.
+    path2: Option<PathBuf>,
+
+    /// More paths to rotate with the first two: each item takes the place (or, with `--name-swap`,
+    /// the name) of the next one, and the last item that of the first.
+    #[arg(value_name = "PATH", conflicts_with_all = [
+        "from_file", "pair_syntax", "by_hash", "attributes_only", "quarantine", "journal", "deref_side", "annotate", "link_marker", "verify", "report_permissions_diff",
+        "show_tree", "relativize_targets",
+    ])]
+    more_paths: Vec<PathBuf>,
+
+    /// Rotate the other way round: each item takes the place (or name) of the previous one.
+    #[arg(long = "reverse")]
+    reverse: bool,
+
+    /// Read both paths from a single `PATH1:PATH2` argument. A literal colon is written `\:`
+    /// and a literal backslash `\\`.
+    #[arg(long = "pair-syntax", conflicts_with = "path2")]
+    pair_syntax: bool,
+
+    /// Swap the only path given with the unique file under `--hash-root` whose SHA-256 starts
+    /// with PREFIX (at least 4 hexadecimal digits).
+    #[arg(long = "by-hash", value_name = "PREFIX", value_parser = parse_hash_prefix, conflicts_with_all = ["path2", "pair_syntax"])]
+    by_hash: Option<String>,
+
+    /// The directory searched by `--by-hash`.
+    #[arg(long = "hash-root", value_name = "DIR", default_value = ".", requires = "by_hash")]
+    hash_root: PathBuf,
+
+    /// Swap names instead of locations.
+    /// If this flag is present, items will be renamed to each other but stay in their original directories.
+    /// By default, items are moved to each other's directories, keeping their original names.
+    #[arg(short = 'n', long = "name-swap")]
+    name_swap: bool,
+
+	/// Add verbose to log advanced information in the console.
+    #[arg(short = 'v', long = "verbose")]
+    verbose: bool,
+
+    /// Once the swap is done, print where each item ended up, and nothing of the steps: overrides
+    /// `--verbose`.
+    #[arg(long = "summary", conflicts_with_all = ["json", "emit_mmv"])]
+    summary: bool,
+
+    /// Swap symlinks themselves instead of their targets, and never resolve symlinks in the given paths.
+    #[arg(short = 'P', long = "no-dereference")]
+    no_dereference: bool,
+
+    /// When a directory has to be copied to another filesystem, recreate the files hard-linked
+    /// to each other in it as hard links to a single copy, like `cp -a` (on by default).
+    #[arg(long = "preserve-hardlinks", value_name = "BOOL", default_value_t = true, num_args = 0..=1,
+          default_missing_value = "true", action = clap::ArgAction::Set)]
+    preserve_hardlinks: bool,
+
+    /// Refuse any swap that cannot be done in a single atomic exchange, leaving both items untouched,
+    /// rather than falling back to renames through a temporary name.
+    #[arg(long = "atomic-only", conflicts_with = "attributes_only")]
+    atomic_only: bool,
+
+    /// Instead of swapping, print the `systemd-run` command running this same swap in a transient
+    /// unit, or run that command with `=run`.
+    #[arg(long = "via-systemd-run", value_enum, value_name = "MODE", num_args = 0..=1, require_equals = true,
+          default_missing_value = "print")]
+    via_systemd_run: Option<SystemdRun>,
+
+    /// Set this umask (in octal, e.g. `027`) while swapping, for the entries `swap` creates itself:
+    /// quarantine directories, lock directories and the journal.
+    #[arg(long = "umask", value_name = "OCTAL", value_parser = parse_umask)]
+    umask: Option<u32>,
+
+    /// When an item has to be copied to another filesystem, copy the POSIX ACLs of every file and
+    /// directory along with their permission bits.
+    #[arg(long = "preserve-acls", conflicts_with = "attributes_only")]
+    preserve_acls: bool,
+
+    /// Choose which paths have their symlinks resolved: `1` or `2` resolves only that side,
+    /// `none` is the same as `--no-dereference`, and `both` is the default.
+    #[arg(long = "deref-side", value_enum, value_name = "SIDE", conflicts_with = "no_dereference")]
+    deref_side: Option<DerefSide>,
+
+    /// Turn warnings about surprising situations into errors.
+    #[arg(long = "strict")]
+    strict: bool,
+
+    /// When a file has to be copied to another filesystem, stage it as an anonymous `O_TMPFILE`
+    /// inode instead of a visible temporary file (Linux only, regular files only).
+    #[arg(long = "concurrency-safe")]
+    concurrency_safe: bool,
+
+    /// When a directory has to be copied to another filesystem, skip the entries that cannot be
+    /// copied and report them, instead of aborting the whole swap (the default).
+    #[arg(long = "best-effort")]
+    best_effort: bool,
+
+    /// Limit the write rate of copies to another filesystem to RATE per second (e.g. `50M`).
+    /// Renames are never throttled.
+    #[arg(long = "bwlimit", value_name = "RATE", value_parser = parse_size)]
+    bwlimit: Option<u64>,
+
+    /// Move any existing entry in the way of a destination into DIR (created if needed) before
+    /// swapping, instead of overwriting it.
+    #[arg(long, value_name = "DIR")]
+    quarantine: Option<PathBuf>,
+
+    /// Swap every pair of paths listed in FILE, one pair per line, stopping at the first failure.
+    #[arg(long = "from-file", value_name = "FILE", conflicts_with_all = ["path1", "path2"])]
+    from_file: Option<PathBuf>,
+
+    /// Instead of swapping, check every pair of the `--from-file` manifest and list together all
+    /// the pairs whose destinations already exist or that would fail. Nothing is changed.
+    #[arg(long = "report-conflicts", requires = "from_file", conflicts_with_all = ["json", "show_tree"])]
+    report_conflicts: bool,
+
+    /// Record every swap done through a temporary name in FILE, synced to disk before the first
+    /// rename, so that `swap recover-journal FILE` can finish or undo it after a crash.
+    #[arg(long = "journal", value_name = "FILE")]
+    journal: Option<PathBuf>,
+
+    /// Format of the `--journal` records: JSON Lines, or tab-separated fields. Both are read back
+    /// by `swap recover-journal`.
+    #[arg(long = "journal-format", value_enum, value_name = "FORMAT", default_value_t = JournalFormat::Jsonl, requires = "journal")]
+    journal_format: JournalFormat,
+
+    /// After the run, update PATH with metrics in the Prometheus textfile collector format
+    /// (swaps, failures, bytes copied, strategies and duration).
+    #[arg(long = "metrics-file", value_name = "PATH")]
+    metrics_file: Option<PathBuf>,
+
+    /// Exchange only the attributes of both items (see `--preserve`), leaving their contents
+    /// and locations untouched.
+    #[arg(long = "attributes-only", conflicts_with_all = ["name_swap", "quarantine"])]
+    attributes_only: bool,
+
+    /// Comma-separated attributes exchanged by `--attributes-only` (all of them by default).
+    #[arg(long = "preserve", value_enum, value_delimiter = ',', value_name = "LIST", requires = "attributes_only")]
+    preserve: Vec<Attribute>,
+
+    /// Set by `swap explain`: the dry run describes the swap instead of listing the moves.
+    #[arg(skip)]
+    explain: bool,
+
+    /// Only print what would be done, without touching the filesystem.
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+
+    /// With `--dry-run`, exit with status 3 if the swap would change anything, and 0 if it would
+    /// not (e.g. both paths name the same file), like `git diff --exit-code`.
+    #[arg(long = "exit-on-pending", requires = "dry_run")]
+    exit_on_pending: bool,
+
+    /// Print the outcome of each swap as a single-line JSON object instead of text, with how the
+    /// items were moved and the warnings; with `--dry-run`, its plan (see `swap print-schema`).
+    #[arg(long = "json", conflicts_with_all = ["show_resolved", "show_tree", "attributes_only"])]
+    json: bool,
+
+    /// With `--dry-run`, print the moves of each swap as input for `mmv` instead of text, to
+    /// review and perform them with it.
+    #[arg(long = "emit-mmv", requires = "dry_run", conflicts_with_all = [
+        "json", "show_resolved", "show_tree", "attributes_only", "quarantine", "chown",
+    ])]
+    emit_mmv: bool,
+
+    /// Print each argument next to the canonical path it resolves to before acting.
+    #[arg(long = "show-resolved")]
+    show_resolved: bool,
+
+    /// Refuse to swap if it would leave less than SIZE free on any involved filesystem (e.g. `500M`, `2G`).
+    #[arg(long = "reserve", value_name = "SIZE", value_parser = parse_size)]
+    reserve: Option<u64>,
+
+    /// Refuse to swap if the items hold more than N entries in total, each item and everything
+    /// below the directories included.
+    #[arg(long = "max-entries", value_name = "N")]
+    max_entries: Option<u64>,
+
+    /// Refuse to swap unless both paths are of the same type (file, directory or symlink).
+    #[arg(long = "require-same-type")]
+    require_same_type: bool,
+
+    /// Refuse to swap unless both paths are of the given type.
+    #[arg(long = "require-type", value_enum, value_name = "TYPE")]
+    require_type: Option<FileKind>,
+
+    /// Refuse to swap unless both paths are on a filesystem of the given type (Linux only).
+    #[arg(long = "require-fstype", value_enum, value_name = "TYPE")]
+    require_fstype: Option<FsType>,
+
+    /// Expand a leading `~` and `$VAR` or `${VAR}` in the paths to swap, for paths that did not go
+    /// through a shell (e.g. in a `--from-file` manifest).
+    #[arg(long = "expand")]
+    expand: bool,
+
+    /// Rewrite the paths to swap starting with OLD to start with NEW instead, before resolving them
+    /// (repeatable; the longest matching OLD wins). Applied after `--expand`.
+    #[arg(long = "prefix-map", value_name = "OLD=NEW", value_parser = parse_prefix_rule)]
+    prefix_map: Vec<PrefixRule>,
+
+    /// Hold a `.swap.lock` directory in every parent directory involved during the swap, so that
+    /// concurrent swaps using it wait for each other. Works on any filesystem.
+    #[arg(long = "lock-dir")]
+    lock_dir: bool,
+
+    /// Swap only if the lock file PATH exists, e.g. created by a deploy orchestrator once the
+    /// previous steps are done.
+    #[arg(long = "require-lock", value_name = "PATH")]
+    require_lock: Option<PathBuf>,
+
+    /// Remove the lock file of `--require-lock` once the swap succeeded (after every pair with
+    /// `--from-file`), so the next step must be sequenced again.
+    #[arg(long = "consume-lock", requires = "require_lock")]
+    consume_lock: bool,
+
+    /// Print the entries of the affected parent directories before and after the swap
+    /// (the predicted state with `--dry-run`).
+    #[arg(long = "show-tree", conflicts_with = "attributes_only")]
+    show_tree: bool,
+
+    /// Ask for confirmation before a swap with a risk of at least this level (overwritten destination
+    /// or mount point: high; copy to another filesystem: medium; quarantine: low).
+    #[arg(long = "risk-threshold", value_enum, value_name = "LEVEL", default_value_t = RiskLevel::High)]
+    risk_threshold: RiskLevel,
+
+    /// Go on with risky swaps without asking for confirmation.
+    #[arg(short = 'y', long = "yes")]
+    yes: bool,
+
+    /// Check that both items have the same contents after the swap. Files copied to another
+    /// filesystem are hashed during the copy and read back; renamed items are hashed before and after.
+    #[arg(long = "verify")]
+    verify: bool,
+
+    /// After the swap, read the first and last byte of every file of both items (and the entries
+    /// of every directory), a cheap check against unreadable or truncated results.
+    #[arg(long = "smoke-test")]
+    smoke_test: bool,
+
+    /// Give both swapped items this owner (and group) after the swap, recursively for directories.
+    /// Names and numeric ids are accepted; `USER:` uses the login group of the user.
+    #[arg(long = "chown", value_name = "USER[:GROUP]", value_parser = parse_owner, conflicts_with = "attributes_only")]
+    chown: Option<Owner>,
+
+    /// Report errors with the messages of GNU `mv` where a pair of `mv` calls would have failed
+    /// the same way, and print nothing on success, for scripts written around `mv`.
+    #[arg(long = "compat-mv")]
+    compat_mv: bool,
+
+    /// If the current directory is one of the swapped items or inside one, print on stderr the `cd`
+    /// command that follows it to its new location (a child process cannot move its shell).
+    #[arg(long = "suggest-cd")]
+    suggest_cd: bool,
+
+    /// Refuse final names that Windows cannot hold (trailing dot or space, reserved names such as
+    /// `CON` or `NUL`, ...): on SMB, FAT, exFAT and NTFS with `auto`, everywhere with `always`.
+    #[arg(long = "windows-names", value_enum, value_name = "WHEN", default_value_t = WindowsNames::Auto)]
+    windows_names: WindowsNames,
+
+    /// When a directory is copied to another filesystem, create its entries in name order, so
+    /// that filesystems listing entries in creation order list them sorted.
+    #[arg(long = "sort-entries")]
+    sort_entries: bool,
+
+    /// Compare the mode and owner of both items before and after the swap, and report any change
+    /// (an error under `--strict`).
+    #[arg(long = "report-permissions-diff", conflicts_with = "attributes_only")]
+    report_permissions_diff: bool,
+
+    /// With `--no-dereference`, rewrite the targets of the swapped symlinks pointing inside the
+    /// directory shared by both items relative to their new location, so that they still point at
+    /// the same entries.
+    #[arg(long = "relativize-targets", conflicts_with_all = ["attributes_only", "emit_mmv"])]
+    relativize_targets: bool,
+
+    /// Record the swap in a `user.swap.last` extended attribute on both swapped items.
+    #[arg(long = "annotate")]
+    annotate: bool,
+
+    /// After the swap, record the counterpart of each item in a marker file of this name in its
+    /// directory (removed by `swap remove-markers`).
+    #[arg(long = "link-marker", value_name = "NAME", value_parser = marker::parse_name)]
+    link_marker: Option<String>,
+
+    /// Normalize the names of the swapped items (uses the `slug` mode unless `--normalize` is given).
+    #[arg(long = "normalize-names")]
+    normalize_names: bool,
+
+    /// Normalization mode applied to the resulting names. Implies `--normalize-names`.
+    #[arg(long = "normalize", value_enum, value_name = "MODE")]
+    normalize: Option<NormalizeMode>,
+}
+
+/// Subcommands that complement the swap itself.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the last swap recorded on a path by `--annotate`.
+    History {
+        /// The path to inspect.
+        path: PathBuf,
+    },
+    /// Complete or roll back the swaps a crash interrupted, as recorded by `--journal`.
+    RecoverJournal {
+        /// The journal file given to `--journal`.
+        journal: PathBuf,
+    },
+    /// Remove the `--lock-dir` locks a killed swap left behind in the given directories.
+    RecoverLocks {
+        /// The directories holding the locks.
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+        /// Only remove locks held for at least this many seconds.
+        #[arg(long = "stale-after", value_name = "SECONDS", default_value_t = 600)]
+        stale_after: u64,
+    },
+    /// Remove the marker files `--link-marker` left in the given directories.
+    RemoveMarkers {
+        /// The name given to `--link-marker`.
+        #[arg(value_parser = marker::parse_name)]
+        name: String,
+        /// The directories holding the markers.
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+    },
+    /// Print the JSON Schema of the plans printed by `--dry-run --json` (or of the outcomes).
+    PrintSchema {
+        /// Print the schema of the outcomes printed by `--json` without `--dry-run` instead.
+        #[arg(long = "outcome")]
+        outcome: bool,
+    },
+    /// Swap each time a trigger file is created or touched, removing it, until interrupted.
+    Watch {
+        /// The trigger file to watch for.
+        trigger: PathBuf,
+        /// The two paths and the flags of the swap, as they would be given to `swap`.
+        #[arg(required = true, num_args = 2.., trailing_var_arg = true, allow_hyphen_values = true, value_name = "ARG")]
+        args: Vec<OsString>,
+    },
+    /// Describe in plain English what a swap would do, without changing anything.
+    Explain {
+        /// The two paths and the flags of the swap to explain, as they would be given to `swap`.
+        #[arg(required = true, num_args = 2.., trailing_var_arg = true, allow_hyphen_values = true, value_name = "ARG")]
+        args: Vec<OsString>,
+    },
+}
+
+/// The built-in name normalization modes.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum NormalizeMode {
+    /// Lowercase the name.
+    Lowercase,
+    /// Lowercase the name, turn whitespace into underscores and drop unusual characters.
+    Slug,
+}
+
+/// The kind of a filesystem entry, as seen without following a final symlink.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Dir,
+    /// A symbolic link.
+    Symlink,
+    /// Anything else (sockets, FIFOs, device nodes, ...).
+    #[value(skip)]
+    Other,
+}
+
+impl FileKind {
+    fn of(metadata: &fs::Metadata) -> Self {
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            FileKind::Other
+        }
+    }
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FileKind::File => "file",
+            FileKind::Dir => "directory",
+            FileKind::Symlink => "symlink",
+            FileKind::Other => "special file",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The paths whose symlinks are resolved, for `--deref-side`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum DerefSide {
+    /// Neither path: symlinks are swapped themselves.
+    None,
+    /// Only the first path.
+    #[value(name = "1")]
+    First,
+    /// Only the second path.
+    #[value(name = "2")]
+    Second,
+    /// Both paths.
+    Both,
+}
+
+/// The attributes `--attributes-only` can exchange.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Attribute {
+    /// Permission bits, including the set-user-ID, set-group-ID and sticky bits.
+    Mode,
+    /// Owner and group.
+    Ownership,
+    /// Access and modification times.
+    Timestamps,
+    /// Extended attributes of the `user` namespace.
+    Xattrs,
+    /// POSIX ACLs (access ACL, and default ACL of a directory).
+    Acls,
+}
+
+impl Attribute {
+    const ALL: &'static [Attribute] = &[Attribute::Mode, Attribute::Ownership, Attribute::Timestamps, Attribute::Xattrs, Attribute::Acls];
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Attribute::Mode => "mode",
+            Attribute::Ownership => "ownership",
+            Attribute::Timestamps => "timestamps",
+            Attribute::Xattrs => "xattrs",
+            Attribute::Acls => "acls",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The filesystem types `--require-fstype` can check, identified by their `statfs` magic number.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum FsType {
+    /// ext2, ext3 or ext4, which share the same magic number.
+    Ext4,
+    Xfs,
+    Btrfs,
+    Tmpfs,
+    Zfs,
+    Nfs,
+    F2fs,
+    Overlay,
+    /// Any FUSE filesystem: sshfs, rclone mounts, ...
+    Fuse,
+}
+
+impl FsType {
+    fn magic(self) -> i64 {
+        match self {
+            FsType::Ext4 => sys::EXT4_SUPER_MAGIC,
+            FsType::Xfs => sys::XFS_SUPER_MAGIC,
+            FsType::Btrfs => sys::BTRFS_SUPER_MAGIC,
+            FsType::Tmpfs => sys::TMPFS_MAGIC,
+            FsType::Zfs => sys::ZFS_SUPER_MAGIC,
+            FsType::Nfs => sys::NFS_SUPER_MAGIC,
+            FsType::F2fs => sys::F2FS_SUPER_MAGIC,
+            FsType::Overlay => sys::OVERLAYFS_SUPER_MAGIC,
+            FsType::Fuse => sys::FUSE_SUPER_MAGIC,
+        }
+    }
+
+    /// Returns a readable name for a magic number, falling back to its hexadecimal value.
+    fn describe(magic: i64) -> String {
+        FsType::value_variants()
+            .iter()
+            .find(|fs_type| fs_type.magic() == magic)
+            .and_then(|fs_type| fs_type.to_possible_value())
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_else(|| format!("{:#x}", magic))
+    }
+}
+
+/// The ownership given by `--chown`, resolved to numeric ids. `None` leaves that id unchanged.
+#[derive(Clone, Debug)]
+struct Owner {
+    spec: String,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+/// A `--prefix-map` rule: paths starting with `old` are rewritten to start with `new`.
+#[derive(Clone, Debug)]
+struct PrefixRule {
+    old: PathBuf,
+    new: PathBuf,
+}
+
+/// When `--windows-names` checks the final names.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum WindowsNames {
+    /// On the filesystems following the Windows naming rules.
+    Auto,
+    /// On every filesystem.
+    Always,
+    /// Never.
+    Never,
+}
+
+/// The record formats of `--journal-format`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum JournalFormat {
+    /// One JSON object per line.
+    Jsonl,
+    /// Tab-separated fields, with `\`, tabs and newlines escaped.
+    Tsv,
+}
+
+/// What `--via-systemd-run` does with the `systemd-run` command.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SystemdRun {
+    /// Print the command.
+    Print,
+    /// Run the command and wait for the unit to finish.
+    Run,
+}
+
+/// How much a swap could surprise the user or cost them data, as assessed by `risk::assess`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RiskLevel::Low => "low",
+            RiskLevel::Medium => "medium",
+            RiskLevel::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Cli {
+    /// Returns whether symlinks are resolved in the first (`side` 1) or second (`side` 2) path.
+    fn dereferences(&self, side: usize) -> bool {
+        match (self.deref_side, self.no_dereference) {
+            (Some(DerefSide::Both), _) => true,
+            (Some(DerefSide::First), _) => side == 1,
+            (Some(DerefSide::Second), _) => side == 2,
+            (Some(DerefSide::None), _) | (None, true) => false,
+            (None, false) => true,
+        }
+    }
+
+    /// Returns the two paths to swap, split from the single argument under `--pair-syntax`,
+    /// or `None` if they were not given (with `--from-file`).
+    fn paths(&self) -> Result<Option<(PathBuf, PathBuf)>, SwapError> {
+        match (&self.path1, &self.path2) {
+            (Some(pair), None) if self.pair_syntax => split_pair(pair.as_os_str()).map(Some),
+            (Some(dash), None) if dash.as_os_str() == "-" && self.by_hash.is_none() => read_stdin_pair().map(Some),
+            (Some(path), None) => match &self.by_hash {
+                Some(prefix) => Ok(Some((find_by_hash(prefix, &self.hash_root, path, self)?, path.clone()))),
+                None => Ok(None),
+            },
+            (Some(path1), Some(path2)) => Ok(Some((path1.clone(), path2.clone()))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the normalization mode to apply, if any.
+    fn normalize_mode(&self) -> Option<NormalizeMode> {
+        match (self.normalize, self.normalize_names) {
+            (Some(mode), _) => Some(mode),
+            (None, true) => Some(NormalizeMode::Slug),
+            (None, false) => None,
+        }
+    }
+}
+
+// --- Main Application Logic ---
+
+/// Exit status of `--dry-run --exit-on-pending` when the swap would change something.
+const EXIT_PENDING: i32 = 3;
+
+/// Set by the dry run of a swap that would change something, for `--exit-on-pending`.
+static CHANGES_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Every warning printed so far, for the outcomes printed by `--json`.
+static WARNINGS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+/// Runs the command line of the process, and exits with its status on failure.
+pub fn main() {
+    let mut cli = Cli::parse();
+    let started = std::time::Instant::now();
+
+    let result = match &cli.command {
+        Some(Command::History { path }) => print_history(path),
+        Some(Command::Explain { args }) => explain(args),
+        Some(Command::Watch { trigger, args }) => watch::watch(trigger, args),
+        Some(Command::RecoverJournal { journal }) => journal::recover(journal, &cli),
+        Some(Command::RecoverLocks { dirs, stale_after }) => lock::recover(dirs, std::time::Duration::from_secs(*stale_after)),
+        Some(Command::RemoveMarkers { name, dirs }) => marker::remove(name, dirs),
+        Some(Command::PrintSchema { outcome }) => {
+            println!("{}", if *outcome { plan::outcome_schema() } else { plan::schema() });
+            Ok(())
+        }
+        None if cli.via_systemd_run.is_some() => cli.via_systemd_run.map_or(Ok(()), systemd::hand_over),
+        None if cli.from_file.is_some() => run_batch(&mut cli),
+        None => cli.paths().and_then(|paths| {
+            let Some((path1, path2)) = paths else {
+                let message = "the second path is required, unless the first one is `-` (or with --pair-syntax or --by-hash)";
+                <Cli as clap::CommandFactory>::command().error(clap::error::ErrorKind::MissingRequiredArgument, message).exit();
+            };
+            // Ctrl-C only raises this flag: the swap stops between two steps and rolls back cleanly.
+            let cancel = sys::interrupt_flag();
+            let swapped = match run(&cli, &path1, &path2, cancel) {
+                Err(e) if (cli.exit_on_pending && is_no_op(&e)) || matches!(e, SwapError::HardLinksNoOp { .. }) => {
+                    // Up to date: the reason is only informative.
+                    println!("{}", e.to_string().trim_start_matches("Error: "));
+                    return Ok(());
+                }
+                result => result,
+            };
+            swapped.map(|()| {
+                if cli.json || cli.emit_mmv {
+                    // The plan or the outcome is the whole output, for the consumer to parse.
+                } else if cli.dry_run {
+                    println!("Dry run: nothing was changed.");
+                } else if !cli.compat_mv {
+                    println!("Swap successful!");
+                }
+                consume_deploy_lock(&cli);
+            })
+        }),
+    };
+
+    if let Some(path) = &cli.metrics_file {
+        if let Err(e) = metrics::write(path, result.is_err(), started.elapsed()) {
+            warn(format!("could not write metrics: {}", e));
+        }
+    }
+
+    if let Err(e) = result {
+        if cli.compat_mv {
+            eprintln!("{}", compat::mv_message(&e, &cli));
+        } else {
+            eprintln!("{}", e);
+        }
+        let code = if matches!(e, SwapError::Cancelled) { 130 } else { 1 };
+        std::process::exit(code);
+    }
+    if cli.exit_on_pending && CHANGES_PENDING.load(std::sync::atomic::Ordering::SeqCst) {
+        std::process::exit(EXIT_PENDING);
+    }
+}
+
+/// Returns true for the errors of a swap that would not change anything, which `--exit-on-pending`
+/// accepts as an up-to-date layout.
+fn is_no_op(error: &SwapError) -> bool {
+    matches!(error, SwapError::SamePath | SwapError::SameInode { .. } | SwapError::HardLinksNoOp { .. })
+}
+
+/// Swaps the paths of the command line `swap ARGS...` without the final message, where the first
+/// of `args` is the program name. Lets the benchmarks time a swap without starting a process.
+pub fn swap<I, T>(args: I) -> Result<(), SwapError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let cli = Cli::parse_from(args);
+    let Some((path1, path2)) = cli.paths()? else {
+        clap::Error::raw(clap::error::ErrorKind::MissingRequiredArgument, "`swap` needs the two paths to swap\n").exit();
+    };
+    run(&cli, &path1, &path2, &AtomicBool::new(false))
+}
+
+/// Runs the validation and planning of the swap described by `args` as a dry run,
+/// printing a narrative of what the swap would do instead of the planned moves.
+fn explain(args: &[OsString]) -> Result<(), SwapError> {
+    let program = OsString::from("swap");
+    let mut cli = Cli::parse_from(std::iter::once(&program).chain(args));
+    let Some((path1, path2)) = cli.paths()? else {
+        clap::Error::raw(clap::error::ErrorKind::MissingRequiredArgument, "`swap explain` needs the two paths to swap\n").exit();
+    };
+
+    cli.dry_run = true;
+    cli.explain = true;
+    cli.metrics_file = None;
+    run(&cli, &path1, &path2, &AtomicBool::new(false))
+}
+
+/// Swaps every pair of the `--from-file` manifest in order, stopping at the first failure.
+/// Pairs swapped before the failure are left swapped.
+fn run_batch(cli: &mut Cli) -> Result<(), SwapError> {
+    let Some(manifest) = cli.from_file.clone() else {
+        return Ok(());
+    };
+    let pairs = manifest::read(&manifest, cli)?;
+    if cli.report_conflicts {
+        return report_conflicts(&manifest, &pairs, cli);
+    }
+    let cancel = sys::interrupt_flag();
+
+    for (index, (path1, path2)) in pairs.iter().enumerate() {
+        match run(cli, path1, path2, cancel) {
+            Err(e) if cli.exit_on_pending && is_no_op(&e) => continue,
+            Err(e @ SwapError::HardLinksNoOp { .. }) => println!("{}", e.to_string().trim_start_matches("Error: ")),
+            Err(e) => {
+                eprintln!("Swapping '{}' and '{}' failed, {} of {} pairs swapped.", path1.display(), path2.display(), index, pairs.len());
+                return Err(e);
+            }
+            Ok(()) => log!(cli, "Swapped '{}' and '{}'.", path1.display(), path2.display()),
+        }
+    }
+
+    if cli.json || cli.emit_mmv {
+        // One plan or outcome per line: JSON Lines (or one move per line for `mmv`).
+    } else if cli.dry_run {
+        println!("Dry run: nothing was changed.");
+    } else if !cli.compat_mv {
+        println!("{} swaps successful!", pairs.len());
+    }
+    consume_deploy_lock(cli);
+    Ok(())
+}
+
+/// Implements `--report-conflicts`: plans every pair of the manifest as a dry run, and lists
+/// together the pairs whose destinations are taken and the ones that would fail.
+/// Every pair is checked against the current state, not the one left by the previous pairs.
+fn report_conflicts(manifest: &Path, pairs: &[(PathBuf, PathBuf)], cli: &mut Cli) -> Result<(), SwapError> {
+    cli.dry_run = true;
+    let cancel = AtomicBool::new(false);
+
+    let mut conflicts = Vec::new();
+    for (index, (path1, path2)) in pairs.iter().enumerate() {
+        if let Err(e) = run(cli, path1, path2, &cancel) {
+            let message = e.to_string();
+            let message = message.strip_prefix("Error: ").unwrap_or(&message).to_string();
+            conflicts.push(format!("  pair {}: '{}' <-> '{}': {}", index + 1, path1.display(), path2.display(), message));
+        }
+    }
+
+    if conflicts.is_empty() {
+        println!("No conflicts in the {} pairs of '{}'.", pairs.len(), manifest.display());
+        return Ok(());
+    }
+    println!("Conflicts in '{}':", manifest.display());
+    for conflict in &conflicts {
+        println!("{}", conflict);
+    }
+    Err(SwapError::ConflictsFound { conflicting: conflicts.len(), pairs: pairs.len() })
+}
+
+/// The core function that executes the swapping logic.
+/// Setting `cancel` (from another thread or a signal handler) aborts the swap between two steps.
+fn run(cli: &Cli, arg1: &Path, arg2: &Path, cancel: &AtomicBool) -> Result<(), SwapError> {
+    let _umask = cli.umask.map(sys::Umask::set);
+    check_deploy_lock(cli)?;
+    let (arg1, arg2) = (prepare_arg(arg1, cli)?, prepare_arg(arg2, cli)?);
+    let (arg1, arg2) = (arg1.as_path(), arg2.as_path());
+    if !cli.more_paths.is_empty() {
+        let more = cli.more_paths.iter().map(|arg| prepare_arg(arg, cli)).collect::<Result<Vec<_>, _>>()?;
+        let args: Vec<&Path> = [arg1, arg2].into_iter().chain(more.iter().map(PathBuf::as_path)).collect();
+        check_chown_permitted(cli)?;
+        return rotate::rotate(cli, &args, cancel);
+    }
+
+    // --- 1. Input Validation and Path Canonicalization ---
+    preflight(&[arg1, arg2], cli)?;
+
+    // Helper closure to map IO errors correctly. This resolves the warning.
+    let map_canonicalize_error = |e: io::Error, path: &Path| {
+        if e.kind() == io::ErrorKind::NotFound {
+            SwapError::PathNotFound(path.to_path_buf())
+        } else {
+            SwapError::Io(e, path.to_path_buf())
+        }
+    };
+    
+    // `canonicalize` resolves symlinks, `..`, `.` and returns an absolute path.
+    // With `--no-dereference` (or `--deref-side` excluding a side) the paths are only made absolute,
+    // so symlinks are swapped themselves.
+    // We now check specifically for `NotFound` errors.
+    let resolve = |arg: &Path, side| if cli.dereferences(side) { fs::canonicalize(arg) } else { absolute_lexical(arg) };
+    let mut path1 = resolve(arg1, 1)
+        .map_err(|e| map_canonicalize_error(e, arg1))?;
+    let mut path2 = resolve(arg2, 2)
+        .map_err(|e| map_canonicalize_error(e, arg2))?;
+
+    let (mut arg1, mut arg2) = (arg1.to_path_buf(), arg2.to_path_buf());
+    match (has_trailing_separator(&arg1), has_trailing_separator(&arg2)) {
+        (false, true) => {
+            if let Some((arg, path)) = into_directory(&arg2, &path2, &path1, cli)? {
+                (arg2, path2) = (arg, path);
+            }
+        }
+        (true, false) => {
+            if let Some((arg, path)) = into_directory(&arg1, &path1, &path2, cli)? {
+                (arg1, path1) = (arg, path);
+            }
+        }
+        // Two directories written alike (`swap a/ b/`) are simply swapped.
+        _ => {}
+    }
+
+    if cli.show_resolved {
+        println!("'{}' -> '{}'", arg1.display(), path1.display());
+        println!("'{}' -> '{}'", arg2.display(), path2.display());
+    }
+
+    // Check if the user is trying to swap a path with itself.
+    if path1 == path2 {
+        return Err(SwapError::SamePath);
+    }
+    // The same entry under two spellings, e.g. `Foo` and `foo` on a case-insensitive volume, or a
+    // composed and a decomposed `é` on a normalizing one (APFS and HFS+), where the swap would
+    // rename the entry onto itself. Byte comparisons miss them: the inodes tell.
+    if is_same_entry(&path1, &path2) {
+        return Err(SwapError::SameInode { path1, path2 });
+    }
+    if are_hard_links_in_same_dir(&path1, &path2) {
+        return Err(SwapError::HardLinksNoOp { path1, path2 });
+    }
+
+    // A critical safety check: prevent swapping a directory with its own child.
+    if path1.is_dir() && path2.starts_with(&path1) {
+        return Err(SwapError::SwapIntoSubdirectory);
+    }
+    if path2.is_dir() && path1.starts_with(&path2) {
+        return Err(SwapError::SwapIntoSubdirectory);
+    }
+    // The same check by identity, for a directory reached through another route (bind mount, or a
+    // symlink left unresolved by `--no-dereference`) that the string prefix misses.
+    if is_inside_by_identity(&path1, &path2) || is_inside_by_identity(&path2, &path1) {
+        return Err(SwapError::SwapIntoSubdirectory);
+    }
+
+    check_required_types(&[&arg1, &arg2], cli)?;
+    check_required_fstype(&[&path1, &path2], cli)?;
+    check_fuse(&[&path1, &path2], cli)?;
+    check_chown_permitted(cli)?;
+    if cli.attributes_only {
+        // Attributes are not compared: an exchange always counts as a change.
+        CHANGES_PENDING.store(true, std::sync::atomic::Ordering::SeqCst);
+        return attributes::exchange(&path1, &path2, cli);
+    }
+    let pair = PathPair::new(&path1, &path2)?;
+    let (dir1, dir2) = if cli.name_swap { (pair.parent1, pair.parent2) } else { (pair.parent2, pair.parent1) };
+    check_free_space_reserve(&[(&path1, dir1), (&path2, dir2)], cli)?;
+    check_entry_limit(&[&path1, &path2], cli)?;
+
+    // --- 2. Dispatch to the Correct Swap Logic ---
+
+	log!(cli, "Swapping '{}' and '{}'...", path1.display(), path2.display());
+
+	if cli.name_swap {
+	    log!(cli, "Mode: Swapping names.");
+	    swap_names(&path1, &path2, cancel, cli)
+	} else {
+	    log!(cli, "Mode: Swapping locations.");
+	    swap_locations(&path1, &path2, cancel, cli)
+	}
+}
+
+/// The two canonical paths of a swap, split into their parents and file names.
+/// Computed once so that every swap mode works from the same, validated pieces.
+struct PathPair<'a> {
+    path1: &'a Path,
+    path2: &'a Path,
+    parent1: &'a Path,
+    parent2: &'a Path,
+    name1: &'a OsStr,
+    name2: &'a OsStr,
+}
+
+impl<'a> PathPair<'a> {
+    fn new(path1: &'a Path, path2: &'a Path) -> Result<Self, SwapError> {
+        let parent1 = path1.parent().ok_or_else(|| SwapError::MissingParent(path1.to_path_buf()))?;
+        let parent2 = path2.parent().ok_or_else(|| SwapError::MissingParent(path2.to_path_buf()))?;
+
+        let name1 = path1.file_name().ok_or_else(|| SwapError::MissingParent(path1.to_path_buf()))?;
+        let name2 = path2.file_name().ok_or_else(|| SwapError::MissingParent(path2.to_path_buf()))?;
+
+        Ok(PathPair { path1, path2, parent1, parent2, name1, name2 })
+    }
+}
+
+/// Swaps the locations of two paths.
+fn swap_locations(path1: &Path, path2: &Path, cancel: &AtomicBool, cli: &Cli) -> Result<(), SwapError> {
+    perform_swap(path1, path2, "Moving", cancel, |pair| {
+        (
+            pair.parent2.join(normalized_name(pair.name1, cli)),
+            pair.parent1.join(normalized_name(pair.name2, cli)),
+        )
+    }, cli)
+}
+
+/// Swaps the names of two paths.
+fn swap_names(path1: &Path, path2: &Path, cancel: &AtomicBool, cli: &Cli) -> Result<(), SwapError> {
+    perform_swap(path1, path2, "Renaming", cancel, |pair| {
+        (
+            pair.parent1.join(normalized_name(pair.name2, cli)),
+            pair.parent2.join(normalized_name(pair.name1, cli)),
+        )
+    }, cli)
+}
+
+/// Runs the three-step rename dance shared by every swap mode.
+/// `dest_fn` returns the final destinations of `path1` and `path2`, in that order.
+/// `verb` is only used to describe the steps in verbose mode.
+/// `cancel` is checked before every step; once set, the completed steps are rolled back.
+fn perform_swap<F>(path1: &Path, path2: &Path, verb: &str, cancel: &AtomicBool, dest_fn: F, cli: &Cli) -> Result<(), SwapError>
+where
+    F: FnOnce(&PathPair) -> (PathBuf, PathBuf),
+{
+    let start = OutcomeStart::now();
+    let pair = PathPair::new(path1, path2)?;
+    let (final_dest1, final_dest2) = dest_fn(&pair);
+    check_normalization_collisions(pair.path1, pair.path2, &final_dest1, &final_dest2, cli)?;
+    check_destination_parents(&[&final_dest1, &final_dest2], cli)?;
+    windows::check(&[(pair.path1, &final_dest1), (pair.path2, &final_dest2)], cli)?;
+
+    let in_the_way: Vec<&Path> = [final_dest1.as_path(), final_dest2.as_path()]
+        .into_iter()
+        .filter(|dest| *dest != pair.path1 && *dest != pair.path2 && dest.symlink_metadata().is_ok())
+        .collect();
+
+    if cli.explain {
+        println!("{}", describe_swap(&pair, &final_dest1, &final_dest2, &in_the_way, cli)?);
+        return Ok(());
+    }
+    if cli.atomic_only && (final_dest1 != pair.path2 || final_dest2 != pair.path1) {
+        return Err(SwapError::NotAtomic("the items do not trade places, which takes renames through a temporary name".to_string()));
+    }
+    let parents = [pair.parent1, pair.parent2, parent_of(&final_dest1)?, parent_of(&final_dest2)?];
+    let before = if cli.show_tree { Some(tree::Snapshot::take(&parents)?) } else { None };
+    let moves_anything = final_dest1 != pair.path1 || final_dest2 != pair.path2 || !in_the_way.is_empty();
+    if cli.dry_run && (moves_anything || cli.chown.is_some()) {
+        CHANGES_PENDING.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+    if cli.report_conflicts && !in_the_way.is_empty() {
+        return Err(SwapError::DestinationsTaken(in_the_way.iter().map(|dest| dest.to_path_buf()).collect()));
+    }
+    if cli.report_conflicts {
+        return Ok(());
+    }
+    let mode = if cli.name_swap { plan::Mode::Name } else { plan::Mode::Location };
+    let moves = || vec![
+        plan::Move { from: pair.path1.to_path_buf(), to: final_dest1.clone() },
+        plan::Move { from: pair.path2.to_path_buf(), to: final_dest2.clone() },
+    ];
+    if cli.dry_run && (cli.json || cli.emit_mmv) {
+        let moves = moves();
+        if cli.emit_mmv {
+            return mmv::print(&moves);
+        }
+        let risks = risk::assess(&pair, &final_dest1, &final_dest2, &in_the_way, cli)?;
+        let rollback_hazards: Vec<String> = risks.iter().filter(|risk| risk.breaks_rollback).map(|risk| risk.reason.clone()).collect();
+        let plan = plan::Plan {
+            version: plan::Version,
+            mode,
+            moves,
+            quarantined: if cli.quarantine.is_some() { in_the_way.iter().map(|dest| dest.to_path_buf()).collect() } else { Vec::new() },
+            rollback_safe: rollback_hazards.is_empty(),
+            rollback_hazards,
+            risks: risks.into_iter().map(|risk| plan::Risk { level: risk.level, reason: risk.reason }).collect(),
+        };
+        println!("{}", plan.to_json());
+        return Ok(());
+    }
+    let relinks = if cli.relativize_targets {
+        relink::plan(&[(pair.path1, final_dest1.as_path()), (pair.path2, final_dest2.as_path())])?
+    } else {
+        Vec::new()
+    };
+    if cli.dry_run {
+        if let Some(dir) = &cli.quarantine {
+            for dest in &in_the_way {
+                println!("Would quarantine '{}' into '{}'", dest.display(), dir.display());
+            }
+        }
+        println!("Would move '{}' -> '{}'", pair.path1.display(), final_dest1.display());
+        println!("Would move '{}' -> '{}'", pair.path2.display(), final_dest2.display());
+        if let Some(owner) = &cli.chown {
+            println!("Would change the ownership of both items to {}", owner.spec);
+        }
+        for relink in &relinks {
+            println!("Would rewrite the target of '{}': '{}' -> '{}'", relink.link.display(), relink.old_target.display(), relink.new_target.display());
+        }
+        let risks = risk::assess(&pair, &final_dest1, &final_dest2, &in_the_way, cli)?;
+        let hazards: Vec<&str> = risks.iter().filter(|risk| risk.breaks_rollback).map(|risk| risk.reason.as_str()).collect();
+        if hazards.is_empty() {
+            println!("Rollback safety: SAFE");
+        } else {
+            println!("Rollback safety: NOT SAFE ({})", hazards.join("; "));
+        }
+        let risky: Vec<String> = risk::above_threshold(&risks, cli);
+        if !risky.is_empty() && !cli.yes {
+            println!("Would ask for confirmation: {}", risky.join("; "));
+        }
+        if let Some(before) = &before {
+            // Displaced entries leave first, for the quarantine or to be overwritten.
+            let mut moves: Vec<(&Path, &Path)> = in_the_way.iter().map(|dest| (*dest, Path::new(""))).collect();
+            moves.extend([(pair.path1, final_dest1.as_path()), (pair.path2, final_dest2.as_path())]);
+            tree::print(before, &before.predict(&moves));
+        }
+        return Ok(());
+    }
+    risk::confirm(&risk::assess(&pair, &final_dest1, &final_dest2, &in_the_way, cli)?, cli)?;
+    let _locks = if cli.lock_dir { lock::acquire(&parents, cli)? } else { Vec::new() };
+
+    let mut quarantined = Vec::new();
+    if let Some(dir) = &cli.quarantine {
+        for dest in in_the_way {
+            let target = quarantine(dest, dir, cli)?;
+            if !cli.json {
+                println!("Quarantined '{}' -> '{}'", dest.display(), target.display());
+            }
+            quarantined.push(target);
+        }
+    }
+
+    // Copied items are verified as they are copied; this pass covers the renamed ones.
+    let mut verified = Vec::new();
+    if cli.verify {
+        let copied = copied_items(&pair, &final_dest1, &final_dest2)?;
+        for (path, dest) in [(pair.path1, final_dest1.as_path()), (pair.path2, final_dest2.as_path())] {
+            if !copied.iter().any(|(copied, _)| *copied == path) {
+                verified.push((dest, content_digest(path)?));
+            }
+        }
+    }
+
+    let permissions = if cli.report_permissions_diff {
+        vec![(final_dest1.as_path(), attributes::Permissions::read(pair.path1)?), (final_dest2.as_path(), attributes::Permissions::read(pair.path2)?)]
+    } else {
+        Vec::new()
+    };
+
+    let cwd = if cli.suggest_cd { std::env::current_dir().ok() } else { None };
+    move_items(&pair, &final_dest1, &final_dest2, verb, cancel, cli)?;
+    metrics::add(&metrics::SWAPS, 1);
+    // From here on the swap is done, and a failure is reported as such.
+    for (dest, before) in permissions {
+        report_permissions_diff(dest, before, cli).map_err(failed_after_swap)?;
+    }
+    if let Some(cwd) = cwd {
+        suggest_cd(&cwd, &[(pair.path1, final_dest1.as_path()), (pair.path2, final_dest2.as_path())]);
+    }
+
+    if let Some(before) = &before {
+        tree::print(before, &tree::Snapshot::take(&parents).map_err(failed_after_swap)?);
+    }
+
+    if cli.smoke_test {
+        for dest in [&final_dest1, &final_dest2] {
+            smoke_test(dest, cli).map_err(failed_after_swap)?;
+            log!(cli, "Smoke test passed for '{}'", dest.display());
+        }
+    }
+    for (dest, digest) in verified {
+        if content_digest(dest).map_err(failed_after_swap)? != digest {
+            return Err(failed_after_swap(SwapError::VerificationFailed(dest.to_path_buf())));
+        }
+        log!(cli, "Verified '{}'", dest.display());
+    }
+
+    if let Some(owner) = &cli.chown {
+        for dest in [&final_dest1, &final_dest2] {
+            change_owner(dest, owner).map_err(failed_after_swap)?;
+            log!(cli, "Changed the ownership of '{}' to {}", dest.display(), owner.spec);
+        }
+    }
+    relink::apply(&relinks, cli).map_err(failed_after_swap)?;
+
+    if cli.annotate {
+        annotate(&final_dest1, &final_dest2, cli);
+        annotate(&final_dest2, &final_dest1, cli);
+    }
+    if let Some(name) = &cli.link_marker {
+        marker::record(name, &final_dest1, &final_dest2, cli);
+        marker::record(name, &final_dest2, &final_dest1, cli);
+    }
+    if cli.summary {
+        print_summary(&moves());
+    }
+    if cli.json {
+        println!("{}", start.finish(mode, moves(), quarantined).to_json());
+    }
+    Ok(())
+}
+
+/// Implements `--report-permissions-diff` for an item now at `dest`: prints how its mode and owner
+/// differ from `before`, or fails under `--strict`.
+fn report_permissions_diff(dest: &Path, before: Option<attributes::Permissions>, cli: &Cli) -> Result<(), SwapError> {
+    let (Some(before), Some(after)) = (before, attributes::Permissions::read(dest)?) else {
+        return Ok(());
+    };
+    let changes = before.changes(&after);
+    if changes.is_empty() {
+        log!(cli, "Permissions of '{}' unchanged", dest.display());
+    } else if cli.strict {
+        return Err(SwapError::PermissionsChanged { path: dest.to_path_buf(), changes });
+    } else {
+        for change in changes {
+            warn(format!("{} on '{}'", change, dest.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the parent directory of a destination.
+fn parent_of(path: &Path) -> Result<&Path, SwapError> {
+    path.parent().ok_or_else(|| SwapError::MissingParent(path.to_path_buf()))
+}
+
+/// Builds the paragraph printed by `swap explain` for a validated swap.
+fn describe_swap(pair: &PathPair, dest1: &Path, dest2: &Path, in_the_way: &[&Path], cli: &Cli) -> Result<String, SwapError> {
+    let item = |path: &Path| -> Result<String, SwapError> {
+        let metadata = path.symlink_metadata().map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+        Ok(format!("the {} '{}' ({})", FileKind::of(&metadata), path.display(), format_size(tree_size(path)?)))
+    };
+    let verb = if cli.name_swap { "rename" } else { "move" };
+    let mut text = format!(
+        "This will {} {} to '{}', and {} to '{}'.",
+        verb, item(pair.path1)?, dest1.display(), item(pair.path2)?, dest2.display()
+    );
+
+    for dest in in_the_way {
+        match &cli.quarantine {
+            Some(dir) => text += &format!(" '{}' is in the way and will first be moved into '{}'.", dest.display(), dir.display()),
+            None => text += &format!(" '{}' already exists and will be replaced.", dest.display()),
+        }
+    }
+
+    let copied = copied_items(pair, dest1, dest2)?;
+
+    if dest1 == pair.path2 && dest2 == pair.path1 {
+        text += " Both entries are exchanged in a single atomic step; if the filesystem cannot do that, \
+                 three renames through a temporary name are used instead.";
+    } else if copied.is_empty() {
+        text += " It takes three renames through a temporary name, each of them atomic.";
+    } else {
+        text += " It takes three steps through a temporary name.";
+    }
+    if copied.is_empty() {
+        text += " No data will be copied.";
+    }
+    for (path, parent) in copied {
+        text += &format!(
+            " '{}' is on another filesystem than '{}', so its {} will be copied and the original removed once the copy is complete.",
+            path.display(), parent.display(), format_size(tree_size(path)?)
+        );
+    }
+    if let Some(owner) = &cli.chown {
+        text += &format!(" Both items will then be owned by {}.", owner.spec);
+    }
+    if cli.annotate {
+        text += &format!(" Both items will be annotated with a `{}` extended attribute.", ANNOTATION_XATTR);
+    }
+    if cli.relativize_targets {
+        text += " Swapped symlinks will have their targets rewritten relative to their new location.";
+    }
+    if let Some(name) = &cli.link_marker {
+        text += &format!(" A `{}` file next to each item will then record its counterpart.", name);
+    }
+    Ok(text)
+}
+
+/// Returns the items that will have to be copied because their destination directory is on
+/// another filesystem, each with that directory.
+fn copied_items<'a>(pair: &PathPair<'a>, dest1: &'a Path, dest2: &'a Path) -> Result<Vec<(&'a Path, &'a Path)>, SwapError> {
+    let device = |path: &Path| sys::device_id(path).map_err(|e| SwapError::Io(e, path.to_path_buf()));
+    let mut copied = Vec::new();
+    for (path, dest) in [(pair.path1, dest1), (pair.path2, dest2)] {
+        let parent = dest.parent().ok_or_else(|| SwapError::MissingParent(dest.to_path_buf()))?;
+        if device(path)? != device(parent)? {
+            copied.push((path, parent));
+        }
+    }
+    Ok(copied)
+}
+
+/// Moves both items to their final destinations, atomically when the platform allows it.
+fn move_items(pair: &PathPair, final_dest1: &Path, final_dest2: &Path, verb: &str, cancel: &AtomicBool, cli: &Cli) -> Result<(), SwapError> {
+    // From here on, every rename is done relative to handles captured right after validation.
+    let mut pinned = PinnedSwap::capture(pair)?;
+    check_cancelled(cancel)?;
+
+    // When each item lands exactly where the other one was, the kernel can do the whole swap atomically.
+    // Nothing of the rename dance happens on this path: no temporary name is generated, and no
+    // journal entry is written, unless the exchange is refused.
+    if final_dest1 == pair.path2 && final_dest2 == pair.path1 {
+        log!(cli, " 1. Exchanging '{}' <-> '{}' (atomic)", pair.path1.display(), pair.path2.display());
+        match pinned.exchange(pair) {
+            Ok(()) => {
+                metrics::add(&metrics::EXCHANGES, 1);
+                return Ok(());
+            }
+            Err(SwapError::Io(e, _)) if sys::is_cross_device(&e) && cli.atomic_only => {
+                return Err(SwapError::NotAtomic("the items are on different filesystems".to_string()));
+            }
+            Err(SwapError::Io(e, _)) if sys::is_unsupported(&e) && cli.atomic_only => {
+                return Err(SwapError::NotAtomic(format!("the filesystem of '{}' does not support atomic exchange", pair.parent1.display())));
+            }
+            Err(SwapError::Io(e, _)) if sys::is_unsupported(&e) || sys::is_cross_device(&e) => {
+                log!(cli, "    Atomic exchange unavailable ({}), falling back to a temporary rename.", e);
+                metrics::add(&metrics::EXCHANGE_REFUSALS, 1);
+                warn_intermediate_states(pair);
+            }
+            Err(e) => return Err(e),
+        }
+    } else {
+        warn_if_snapshotting_fs(pair);
+    }
+
+    // Not a `TempGuard`: the temporary entry is the first item itself, which `roll_back` restores.
+    // The first rename never replaces an entry: if another process created the generated name in
+    // the meantime, another name is tried.
+    let mut attempts = 0;
+    let (temp_path, entry) = loop {
+        let temp_path = generate_temporary_path(pair.path1)?;
+        let intent = journal::Intent { path1: pair.path1, path2: pair.path2, dest1: final_dest1, dest2: final_dest2, temp: &temp_path };
+        let entry = cli.journal.as_deref().map(|journal| journal::begin(journal, cli.journal_format, &intent)).transpose()?;
+        let result = check_cancelled(cancel).and_then(|()| {
+            log!(cli, " 1. {} '{}' -> '{}' (temporary)", verb, pair.path1.display(), temp_path.display());
+            pinned.stage(pair.path1, &temp_path)
+        });
+        match result {
+            Ok(()) => break (temp_path, entry),
+            Err(e) => {
+                if let Some(entry) = entry {
+                    entry.abort()?;
+                }
+                attempts += 1;
+                if !matches!(e, SwapError::TempRaceDetected(_)) || attempts == TEMP_ATTEMPTS {
+                    return Err(e);
+                }
+                metrics::add(&metrics::TEMP_RETRIES, 1);
+                log!(cli, "    '{}' was created by another process, trying another temporary name.", temp_path.display());
+            }
+        }
+    };
+    let steps = [
+        (pair.path2, final_dest2, 1, "", ""),
+        (temp_path.as_path(), final_dest1, 0, "", " (temporary)"),
+    ];
+
+    // Completed steps, kept so they can be undone in reverse order on failure or cancellation.
+    let mut done: Vec<(&Path, &Path, usize)> = vec![(pair.path1, temp_path.as_path(), 0)];
+    for (index, (from, to, item, to_note, from_note)) in steps.into_iter().enumerate() {
+        let result = check_cancelled(cancel).and_then(|()| {
+            log!(cli, " {}. {} '{}'{} -> '{}'{}", index + 2, verb, from.display(), from_note, to.display(), to_note);
+            pinned.rename(from, to, item, Some(cancel), cli)
+        });
+        if let Err(cause) = result {
+            let error = roll_back(&mut pinned, &done, cause, cli);
+            // After a failed rollback, the intent stays open for `swap recover-journal`.
+            if let Some(entry) = entry.filter(|_| !matches!(error, SwapError::RollbackFailed { .. })) {
+                entry.abort()?;
+            }
+            return Err(error);
+        }
+        done.push((from, to, item));
+    }
+
+    if let Some(entry) = entry {
+        entry.commit()?;
+    }
+    metrics::add(&metrics::RENAME_DANCES, 1);
+    Ok(())
+}
+
+/// Number of temporary names tried before giving up on a directory where another process keeps
+/// creating them.
+const TEMP_ATTEMPTS: usize = 3;
+
+/// Name of the extended attribute written by `--annotate`.
+const ANNOTATION_XATTR: &str = "user.swap.last";
+
+/// Records on `path` when it was swapped and where its counterpart now lives.
+/// The swap already happened, so failures are only reported as warnings.
+fn annotate(path: &Path, counterpart: &Path, cli: &Cli) {
+    let value = format!("{} {}", iso_timestamp(std::time::SystemTime::now()), counterpart.display());
+    match sys::set_xattr(path, ANNOTATION_XATTR, value.as_bytes()) {
+        Ok(()) => log!(cli, "Annotated '{}' with {}", path.display(), ANNOTATION_XATTR),
+        Err(e) if sys::is_unsupported(&e) => {
+            log!(cli, "Warning: cannot annotate '{}', user extended attributes are not supported here.", path.display());
+        }
+        Err(e) => warn(format!("could not annotate '{}': {}", path.display(), e)),
+    }
+}
+
+/// Implements `swap history <PATH>`: prints the swap recorded by `--annotate`.
+fn print_history(path: &Path) -> Result<(), SwapError> {
+    let value = sys::get_xattr(path, ANNOTATION_XATTR).map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+    let Some(value) = value else {
+        return Err(SwapError::NoSwapHistory(path.to_path_buf()));
+    };
+
+    let value = String::from_utf8_lossy(&value);
+    match value.split_once(' ') {
+        Some((timestamp, counterpart)) => println!("'{}' was swapped at {} with '{}'.", path.display(), timestamp, counterpart),
+        None => println!("'{}': {}", path.display(), value),
+    }
+    Ok(())
+}
+
+/// Formats a time as an ISO 8601 UTC timestamp with second precision, e.g. `2024-05-01T13:37:00Z`.
+fn iso_timestamp(time: std::time::SystemTime) -> String {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm), valid for any date after 1970.
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
+
+/// Fails with `Cancelled` once the cancellation flag has been raised.
+fn check_cancelled(cancel: &AtomicBool) -> Result<(), SwapError> {
+    if cancel.load(Ordering::SeqCst) {
+        Err(SwapError::Cancelled)
+    } else {
+        Ok(())
+    }
+}
+
+/// Undoes the completed steps in reverse order and returns the error to report.
+fn roll_back(pinned: &mut PinnedSwap, done: &[(&Path, &Path, usize)], cause: SwapError, cli: &Cli) -> SwapError {
+    for (from, to, item) in done.iter().rev() {
+        // An item deleted by another process cannot be moved back; the other one still is.
+        if pinned.is_unlinked(*item) {
+            log!(cli, "    Not rolling back '{}' -> '{}', it was deleted", to.display(), from.display());
+            continue;
+        }
+        log!(cli, "    Rolling back '{}' -> '{}'", to.display(), from.display());
+        if let Err(rollback) = pinned.rename(to, from, *item, None, cli) {
+            return SwapError::RollbackFailed { cause: Box::new(cause), rollback: Box::new(rollback) };
+        }
+    }
+    cause
+}
+
+/// The parent directories and items of a swap, pinned by handle right after validation.
+/// Each rename is relative to the pinned directories and first re-checks that its source entry
+/// is still the inode that was validated, closing the gap between canonicalization and action.
+struct PinnedSwap {
+    dirs: [sys::PinnedDir; 2],
+    /// The item handles, held open for the whole swap.
+    items: [sys::PinnedEntry; 2],
+    /// Current identity of each item. It only differs from the pinned one once a step had to
+    /// copy the item to another filesystem.
+    ids: [sys::FileId; 2],
+}
+
+impl PinnedSwap {
+    fn capture(pair: &PathPair) -> Result<Self, SwapError> {
+        let dir = |path: &Path| sys::PinnedDir::open(path).map_err(|e| SwapError::Io(e, path.to_path_buf()));
+        let item = |path: &Path| sys::PinnedEntry::open(path).map_err(|e| SwapError::Io(e, path.to_path_buf()));
+
+        let items = [item(pair.path1)?, item(pair.path2)?];
+        Ok(PinnedSwap {
+            dirs: [dir(pair.parent1)?, dir(pair.parent2)?],
+            ids: [items[0].id(), items[1].id()],
+            items,
+        })
+    }
+
+    /// Returns the pinned directory holding `path` along with its file name.
+    /// Swap steps only ever touch entries directly inside the two validated parents.
+    fn locate<'p>(&self, path: &'p Path) -> Result<(&sys::PinnedDir, &'p OsStr), SwapError> {
+        let parent = path.parent().ok_or_else(|| SwapError::MissingParent(path.to_path_buf()))?;
+        let name = path.file_name().ok_or_else(|| SwapError::MissingParent(path.to_path_buf()))?;
+        let dir = self.dirs.iter()
+            .find(|dir| dir.path() == parent)
+            .ok_or_else(|| SwapError::MissingParent(path.to_path_buf()))?;
+        Ok((dir, name))
+    }
+
+    /// Fails with `PathChangedUnderneath` unless `path` is still the pinned item `item`, or with
+    /// `PathUnlinkedDuringSwap` if the item was deleted in the meantime.
+    fn verify(&self, path: &Path, item: usize) -> Result<(), SwapError> {
+        let (dir, name) = self.locate(path)?;
+        let current = dir.entry_id(name);
+        if current.as_ref().is_ok_and(|current| *current == self.ids[item]) {
+            return Ok(());
+        }
+        if self.is_unlinked(item) {
+            return Err(SwapError::PathUnlinkedDuringSwap(path.to_path_buf()));
+        }
+        match current {
+            Ok(_) => Err(SwapError::PathChangedUnderneath(path.to_path_buf())),
+            Err(e) => Err(SwapError::Io(e, path.to_path_buf())),
+        }
+    }
+
+    /// Returns true if the pinned item `item` lost its last name. An item copied to another
+    /// filesystem is a new inode, and its pinned original was removed on purpose.
+    fn is_unlinked(&self, item: usize) -> bool {
+        self.ids[item] == self.items[item].id() && self.items[item].link_count().is_ok_and(|count| count == Some(0))
+    }
+
+    /// Renames the first item from `from` to the temporary `to` in the same directory, refusing
+    /// to replace an entry another process may have created there.
+    fn stage(&self, from: &Path, to: &Path) -> Result<(), SwapError> {
+        self.verify(from, 0)?;
+        let (from_dir, from_name) = self.locate(from)?;
+        let (to_dir, to_name) = self.locate(to)?;
+        sys::rename_noreplace_at(from_dir, from_name, to_dir, to_name).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => SwapError::TempRaceDetected(to.to_path_buf()),
+            _ => SwapError::Io(e, from.to_path_buf()),
+        })
+    }
+
+    /// Renames `from`, which must still be the pinned item `item`, to `to`.
+    /// The item is copied when `to` is on another filesystem; `cancel` is checked during the copy.
+    fn rename(&mut self, from: &Path, to: &Path, item: usize, cancel: Option<&AtomicBool>, cli: &Cli) -> Result<(), SwapError> {
+        self.verify(from, item)?;
+        let (from_dir, from_name) = self.locate(from)?;
+        let (to_dir, to_name) = self.locate(to)?;
+
+        let err = match sys::rename_at(from_dir, from_name, to_dir, to_name) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        if !falls_back_to_copy(&err, from_dir, to_dir, to, cli) {
+            return Err(SwapError::Io(err, from.to_path_buf()));
+        }
+        let new_id = copy::move_across(from_dir, from_name, to_dir, to_name, self.ids[item], cancel, cli)?;
+        self.ids[item] = new_id;
+        metrics::add(&metrics::COPIES, 1);
+        Ok(())
+    }
+
+    /// Atomically exchanges both pinned items.
+    fn exchange(&self, pair: &PathPair) -> Result<(), SwapError> {
+        self.verify(pair.path1, 0)?;
+        self.verify(pair.path2, 1)?;
+        sys::exchange_at(&self.dirs[0], pair.name1, &self.dirs[1], pair.name2)
+            .map_err(|e| SwapError::Io(e, pair.path1.to_path_buf()))
+    }
+}
+
+// --- Helper Functions ---
+
+/// Prints a warning on stderr, and records it for the outcome printed by `--json`.
+fn warn(message: String) {
+    eprintln!("Warning: {}", message);
+    WARNINGS.lock().unwrap_or_else(std::sync::PoisonError::into_inner).push(message);
+}
+
+/// Wraps the failure of a step run once the swap is done, so that its message tells the swap
+/// was applied anyway.
+fn failed_after_swap(cause: SwapError) -> SwapError {
+    SwapError::FailedAfterSwap(Box::new(cause))
+}
+
+/// Implements `--summary`: prints where each item of a completed swap ended up.
+fn print_summary(moves: &[plan::Move]) {
+    for item in moves {
+        println!("'{}' -> '{}'", item.from.display(), item.to.display());
+    }
+}
+
+/// The state of the run when a swap starts, to tell what that swap did in its `--json` outcome.
+struct OutcomeStart {
+    counters: metrics::Snapshot,
+    warnings: usize,
+}
+
+impl OutcomeStart {
+    fn now() -> Self {
+        let warnings = WARNINGS.lock().unwrap_or_else(std::sync::PoisonError::into_inner).len();
+        OutcomeStart { counters: metrics::Snapshot::take(), warnings }
+    }
+
+    /// Returns the outcome of the swap that started at `self` and just completed.
+    fn finish(self, mode: plan::Mode, moves: Vec<plan::Move>, quarantined: Vec<PathBuf>) -> plan::Outcome {
+        let counters = metrics::Snapshot::take().since(self.counters);
+        let warnings = WARNINGS.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        plan::Outcome {
+            version: plan::Version,
+            mode,
+            moves,
+            quarantined,
+            strategy: if counters.exchanges > 0 { plan::Strategy::Exchange } else { plan::Strategy::Rename },
+            exchange_refused: counters.exchange_refusals > 0,
+            copies: counters.copies,
+            temp_retries: counters.temp_retries,
+            warnings: warnings.get(self.warnings..).unwrap_or_default().to_vec(),
+        }
+    }
+}
+
+/// Returns whether a rename that failed with `err` can be done by copying instead, logging why:
+/// the destination `to` is on another filesystem, or a FUSE filesystem refused the rename.
+fn falls_back_to_copy(err: &io::Error, from_dir: &sys::PinnedDir, to_dir: &sys::PinnedDir, to: &Path, cli: &Cli) -> bool {
+    if sys::is_cross_device(err) {
+        log!(cli, "    '{}' is on another filesystem, copying it instead.", to.display());
+        return true;
+    }
+    let on_fuse = |dir: &sys::PinnedDir| sys::fs_type(dir.path()).ok() == Some(sys::FUSE_SUPER_MAGIC);
+    if sys::is_rename_refused(err) && (on_fuse(from_dir) || on_fuse(to_dir)) {
+        log!(cli, "    The FUSE filesystem refused to rename to '{}' ({}), copying it instead.", to.display(), err);
+        return true;
+    }
+    false
+}
+
+/// Warns when the temporary rename dance runs on ZFS: a snapshot taken between two of the
+/// renames would capture one item under its temporary name.
+fn warn_if_snapshotting_fs(pair: &PathPair) {
+    for parent in [pair.parent1, pair.parent2] {
+        if sys::fs_type(parent).ok() == Some(sys::ZFS_SUPER_MAGIC) {
+            warn(format!(
+                "'{}' is on ZFS and the swap cannot be done atomically; a snapshot taken during the swap may capture an intermediate state.",
+                parent.display()
+            ));
+            return;
+        }
+    }
+}
+
+/// Warns that a swap expected to be atomic is not, and for how long each path is missing:
+/// `path1` from the first rename to the second one, `path2` from the second rename to the last.
+fn warn_intermediate_states(pair: &PathPair) {
+    warn(format!(
+        "The filesystem cannot exchange '{}' and '{}' atomically. During the swap, each of them briefly does not exist \
+         and the first one is visible under a temporary name, as a snapshot taken meanwhile may capture (--atomic-only refuses such swaps).",
+        pair.path1.display(),
+        pair.path2.display()
+    ));
+}
+
+/// Generates a unique temporary path in the same directory as the original path.
+fn generate_temporary_path(original_path: &Path) -> Result<PathBuf, SwapError> {
+    let parent = original_path.parent().ok_or_else(|| SwapError::MissingParent(original_path.to_path_buf()))?;
+    let original_filename = original_path.file_name().ok_or_else(|| SwapError::MissingParent(original_path.to_path_buf()))?;
+
+    Ok(parent.join(temporary_name(original_filename)))
+}
+
+/// Returns a unique temporary file name derived from `original_filename`. A reserved Windows
+/// device name stays reserved with an extension (`NUL.swap.<uuid>` is `NUL` on SMB shares), so
+/// such names get a leading dot.
+fn temporary_name(original_filename: &OsStr) -> OsString {
+    let original_filename = original_filename.to_str().unwrap_or("temp");
+    let unique_id = uuid::Uuid::new_v4();
+    let prefix = if windows::is_reserved(original_filename) { "." } else { "" };
+    OsString::from(format!("{}{}.swap.{}", prefix, original_filename, unique_id))
+}
+
+/// Removes a scratch entry (a staged copy, a file being written) when dropped, unless the work
+/// it holds was committed. Makes early returns and panics leak-free; the temporary name of the
+/// rename dance holds a user's item and is restored by the rollback instead.
+struct TempGuard {
+    path: PathBuf,
+    armed: bool,
+}
+
+impl TempGuard {
+    fn new(path: PathBuf) -> Self {
+        TempGuard { path, armed: true }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Disarms the guard once the temporary entry was renamed into place.
+    fn commit(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for TempGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let _ = match self.path.symlink_metadata() {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&self.path),
+            Ok(_) => fs::remove_file(&self.path),
+            Err(_) => Ok(()),
+        };
+    }
+}
+
+/// Applies the requested normalization mode (if any) to a file name.
+/// Names that are not valid UTF-8 are left untouched.
+fn normalized_name(name: &OsStr, cli: &Cli) -> OsString {
+    let (Some(mode), Some(name_str)) = (cli.normalize_mode(), name.to_str()) else {
+        return name.to_os_string();
+    };
+
+    let normalized = match mode {
+        NormalizeMode::Lowercase => name_str.to_lowercase(),
+        NormalizeMode::Slug => slugify(name_str),
+    };
+
+    // Never produce an empty (or hidden-only) name: keep the original instead.
+    if normalized.is_empty() || normalized == "." || normalized == ".." {
+        return name.to_os_string();
+    }
+    OsString::from(normalized)
+}
+
+/// `mv`-style trailing slash: when only one argument is an existing directory written with a
+/// trailing slash (`swap notes.txt archive/`), the other item is swapped with its namesake inside
+/// that directory (`archive/notes.txt`) rather than with the directory itself.
+/// Returns the adjusted argument and path, or `None` when `dir` is not a directory.
+fn into_directory(dir_arg: &Path, dir: &Path, other: &Path, cli: &Cli) -> Result<Option<(PathBuf, PathBuf)>, SwapError> {
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+    let name = other.file_name().ok_or_else(|| SwapError::MissingParent(other.to_path_buf()))?;
+
+    let arg = dir_arg.join(name);
+    let inside = dir.join(name);
+    match inside.symlink_metadata() {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(SwapError::PathNotFound(arg)),
+        Err(e) => return Err(SwapError::Io(e, arg)),
+    }
+
+    log!(cli, "'{}' ends with a slash: swapping with '{}' inside it.", dir_arg.display(), inside.display());
+    Ok(Some((arg, inside)))
+}
+
+/// Moves `entry` into the `--quarantine` directory `dir`, under its full path so that entries from
+/// different places do not mix, e.g. `/srv/a/x` goes to `dir/srv/a/x`. A `.N` suffix is added
+/// if that place is already taken. Returns where the entry was moved.
+fn quarantine(entry: &Path, dir: &Path, cli: &Cli) -> Result<PathBuf, SwapError> {
+    let (Some(parent), Some(name)) = (entry.parent(), entry.file_name()) else {
+        return Err(SwapError::MissingParent(entry.to_path_buf()));
+    };
+    let relative: PathBuf = parent.components()
+        .filter(|component| matches!(component, std::path::Component::Normal(_)))
+        .collect();
+    let target_dir = std::path::absolute(dir)
+        .map_err(|e| SwapError::Io(e, dir.to_path_buf()))?
+        .join(relative);
+    fs::create_dir_all(&target_dir).map_err(|e| SwapError::Io(e, target_dir.clone()))?;
+
+    let mut target_name = name.to_os_string();
+    let mut suffix = 1;
+    while target_dir.join(&target_name).symlink_metadata().is_ok() {
+        target_name = name.to_os_string();
+        target_name.push(format!(".{}", suffix));
+        suffix += 1;
+    }
+
+    let open = |path: &Path| sys::PinnedDir::open(path).map_err(|e| SwapError::Io(e, path.to_path_buf()));
+    let (from_dir, to_dir) = (open(parent)?, open(&target_dir)?);
+    match sys::rename_at(&from_dir, name, &to_dir, &target_name) {
+        Ok(()) => {}
+        Err(e) if falls_back_to_copy(&e, &from_dir, &to_dir, &target_dir, cli) => {
+            let id = sys::FileId::of_path(entry).map_err(|e| SwapError::Io(e, entry.to_path_buf()))?;
+            copy::move_across(&from_dir, name, &to_dir, &target_name, id, None, cli)?;
+        }
+        Err(e) => return Err(SwapError::Io(e, entry.to_path_buf())),
+    }
+    Ok(target_dir.join(target_name))
+}
+
+/// Validates a `--by-hash` prefix and lowercases it.
+fn parse_hash_prefix(input: &str) -> Result<String, String> {
+    if input.len() < 4 || input.len() > 64 || !input.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("expected 4 to 64 hexadecimal digits".to_string());
+    }
+    Ok(input.to_ascii_lowercase())
+}
+
+/// Finds the unique regular file under `root` whose SHA-256 starts with `prefix`, without
+/// following symlinks. `other`, the path it is going to be swapped with, is not a candidate.
+fn find_by_hash(prefix: &str, root: &Path, other: &Path, cli: &Cli) -> Result<PathBuf, SwapError> {
+    let other = fs::canonicalize(other).ok();
+    let mut matches = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    let mut hashed = 0;
+
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir).map_err(|e| SwapError::Io(e, dir.clone()))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| SwapError::Io(e, dir.clone()))?;
+            let path = entry.path();
+            let file_type = entry.file_type().map_err(|e| SwapError::Io(e, path.clone()))?;
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if !file_type.is_file() || fs::canonicalize(&path).ok() == other {
+                continue;
+            }
+
+            hashed += 1;
+            let digest = hash::file_sha256(&path).map_err(|e| SwapError::Io(e, path.clone()))?;
+            if digest.starts_with(prefix) {
+                log!(cli, "'{}' matches (sha256 {}).", path.display(), digest);
+                matches.push(path);
+            }
+        }
+    }
+    log!(cli, "Hashed {} files under '{}'.", hashed, root.display());
+
+    match matches.len() {
+        0 => Err(SwapError::HashNotFound { prefix: prefix.to_string(), root: root.to_path_buf() }),
+        1 => Ok(matches.remove(0)),
+        _ => Err(SwapError::HashNotUnique { prefix: prefix.to_string(), matches }),
+    }
+}
+
+/// Applies `--expand`, then `--prefix-map`, to a path argument.
+fn prepare_arg(arg: &Path, cli: &Cli) -> Result<PathBuf, SwapError> {
+    let arg = expand_arg(arg, cli)?;
+    Ok(map_prefix(&arg, &cli.prefix_map).unwrap_or(arg))
+}
+
+/// Rewrites the prefix of `arg` with the `--prefix-map` rule of the longest `old` prefix it starts
+/// with, comparing whole components: `/data` matches `/data/x` but not `/database`.
+/// Returns `None` when no rule matches.
+fn map_prefix(arg: &Path, rules: &[PrefixRule]) -> Option<PathBuf> {
+    // `max_by_key` keeps the last of equal maxima: going backwards, that is the first rule given.
+    let rule = rules.iter().rev()
+        .filter(|rule| arg.starts_with(&rule.old))
+        .max_by_key(|rule| rule.old.components().count())?;
+    let rest = arg.strip_prefix(&rule.old).ok()?;
+    Some(if rest.as_os_str().is_empty() { rule.new.clone() } else { rule.new.join(rest) })
+}
+
+/// Applies `--expand` to a path argument: a leading `~` (alone or followed by `/`) becomes
+/// `$HOME`, and `$NAME` or `${NAME}` the value of that environment variable. A `$` that starts
+/// no variable name is kept. Arguments that are not valid UTF-8 are left alone.
+fn expand_arg(arg: &Path, cli: &Cli) -> Result<PathBuf, SwapError> {
+    let Some(text) = arg.to_str().filter(|_| cli.expand) else {
+        return Ok(arg.to_path_buf());
+    };
+    let variable = |name: &str| {
+        std::env::var(name).map_err(|_| SwapError::UndefinedVariable { arg: arg.as_os_str().to_os_string(), name: name.to_string() })
+    };
+
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&variable("HOME")?);
+        rest = &rest[1..];
+    }
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (name, remainder) = match after.strip_prefix('{').and_then(|braced| braced.split_once('}')) {
+            Some((name, remainder)) => (name, remainder),
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        expanded.push_str(&variable(name)?);
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+
+    if expanded != text {
+        log!(cli, "Expanded '{}' to '{}'", text, expanded);
+    }
+    Ok(PathBuf::from(expanded))
+}
+
+/// Reads the two paths of `swap -` from stdin: one per line, so that they may contain spaces,
+/// or otherwise separated by whitespace.
+fn read_stdin_pair() -> Result<(PathBuf, PathBuf), SwapError> {
+    let mut input = String::new();
+    io::Read::read_to_string(&mut io::stdin(), &mut input).map_err(|e| SwapError::Io(e, PathBuf::from("<stdin>")))?;
+
+    let lines: Vec<&str> = input.lines().map(|line| line.trim_end_matches('\r')).filter(|line| !line.trim().is_empty()).collect();
+    let tokens: Vec<&str> = if lines.len() == 2 { lines } else { input.split_whitespace().collect() };
+    match tokens.as_slice() {
+        [path1, path2] => Ok((PathBuf::from(path1), PathBuf::from(path2))),
+        [] => Err(SwapError::InvalidStdinPaths("found none".to_string())),
+        [_] => Err(SwapError::InvalidStdinPaths("found only one".to_string())),
+        more => Err(SwapError::InvalidStdinPaths(format!("found {}", more.len()))),
+    }
+}
+
+/// Splits a `--pair-syntax` argument at its only unescaped colon, like `a\:b:c` into `a:b` and `c`.
+/// `\:` stands for a literal colon and `\\` for a literal backslash; other backslashes are kept.
+fn split_pair(arg: &OsStr) -> Result<(PathBuf, PathBuf), SwapError> {
+    let invalid = |reason: &str| SwapError::InvalidPairSyntax { arg: arg.to_os_string(), reason: reason.to_string() };
+    let text = arg.to_str().ok_or_else(|| invalid("it is not valid UTF-8"))?;
+
+    let mut paths = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let current = paths.last_mut().expect("never empty");
+        match (c, chars.clone().next()) {
+            ('\\', Some(escaped @ (':' | '\\'))) => {
+                current.push(escaped);
+                chars.next();
+            }
+            (':', _) => paths.push(String::new()),
+            (c, _) => current.push(c),
+        }
+    }
+
+    match paths.as_slice() {
+        [first, second] if !first.is_empty() && !second.is_empty() => Ok((PathBuf::from(first), PathBuf::from(second))),
+        [_, _] => Err(invalid("one of the paths is empty")),
+        [_] => Err(invalid("there is no unescaped ':'")),
+        _ => Err(invalid("there is more than one unescaped ':', escape literal colons as '\\:'")),
+    }
+}
+
+/// Returns true if the path was written with a trailing separator, like `dir/`.
+fn has_trailing_separator(path: &Path) -> bool {
+    path.as_os_str()
+        .as_encoded_bytes()
+        .last()
+        .is_some_and(|&byte| std::path::is_separator(byte as char))
+}
+
+/// Makes `path` absolute and removes `.` and `..` components without resolving any symlink,
+/// like `realpath -s`. Fails with `NotFound` if the entry itself does not exist.
+fn absolute_lexical(path: &Path) -> io::Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in std::path::absolute(path)?.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            std::path::Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized.symlink_metadata()?;
+    Ok(normalized)
+}
+
+/// Returns true if both paths name the same entry of the same directory, comparing the
+/// identities of the entries and of their parent directories: the directory lists only one of
+/// the names, and the filesystem maps the other spelling to it.
+fn is_same_entry(path1: &Path, path2: &Path) -> bool {
+    same_inode_in_same_dir(path1, path2) && !(is_listed(path1) && is_listed(path2))
+}
+
+/// Returns true if both paths are distinct hard links to the same file in the same directory,
+/// which the directory lists under both names.
+fn are_hard_links_in_same_dir(path1: &Path, path2: &Path) -> bool {
+    same_inode_in_same_dir(path1, path2) && is_listed(path1) && is_listed(path2)
+}
+
+fn same_inode_in_same_dir(path1: &Path, path2: &Path) -> bool {
+    let id = |path: &Path| sys::FileId::of_path(path).ok();
+    let parent_id = |path: &Path| path.parent().and_then(|parent| sys::FileId::of_path(parent).ok());
+    id(path1).is_some() && id(path1) == id(path2) && parent_id(path1) == parent_id(path2)
+}
+
+/// Returns true if the directory of `path` lists its name byte for byte.
+fn is_listed(path: &Path) -> bool {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else { return false };
+    fs::read_dir(parent).is_ok_and(|entries| entries.flatten().any(|entry| entry.file_name() == name))
+}
+
+/// Returns true if one of the ancestors of `path` is the directory `dir` itself, comparing
+/// device and inode numbers instead of paths. Ancestors that cannot be inspected are skipped.
+fn is_inside_by_identity(dir: &Path, path: &Path) -> bool {
+    let Ok(metadata) = dir.symlink_metadata() else { return false };
+    if !metadata.is_dir() {
+        return false;
+    }
+    let Ok(dir_id) = sys::FileId::of_metadata(&metadata) else { return false };
+
+    path.ancestors()
+        .skip(1)
+        .filter_map(|ancestor| fs::metadata(ancestor).ok())
+        .filter_map(|metadata| sys::FileId::of_metadata(&metadata).ok())
+        .any(|id| id == dir_id)
+}
+
+/// Detects destinations whose parent directory is reached through a symlink, which would place
+/// the item somewhere other than where the path suggests. This can only happen when a side is
+/// not dereferenced (`--no-dereference`, `--deref-side`), where it is refused, as it is under `--strict`.
+fn check_destination_parents(dests: &[&Path], cli: &Cli) -> Result<(), SwapError> {
+    for dest in dests {
+        let Some(parent) = dest.parent() else { continue };
+        let resolved = fs::canonicalize(parent).map_err(|e| SwapError::Io(e, parent.to_path_buf()))?;
+        if resolved == parent {
+            continue;
+        }
+
+        if !cli.dereferences(1) || !cli.dereferences(2) || cli.strict {
+            return Err(SwapError::DestinationThroughSymlink(dest.to_path_buf()));
+        }
+        log!(cli, "Note: '{}' resolves through a symlink to '{}'", dest.display(), resolved.display());
+    }
+    Ok(())
+}
+
+/// Enforces `--require-fstype` on the filesystems holding every item.
+fn check_required_fstype(paths: &[&Path], cli: &Cli) -> Result<(), SwapError> {
+    let Some(expected) = cli.require_fstype else {
+        return Ok(());
+    };
+
+    for path in paths {
+        let magic = sys::fs_type(path).map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+        if magic != expected.magic() {
+            return Err(SwapError::UnexpectedFsType { path: path.to_path_buf(), found: FsType::describe(magic) });
+        }
+        log!(cli, "'{}' is on {} as required.", path.display(), FsType::describe(magic));
+    }
+    Ok(())
+}
+
+/// Refuses the swap when the lock file of `--require-lock` does not exist. Any kind of entry
+/// counts, a dangling symlink included.
+fn check_deploy_lock(cli: &Cli) -> Result<(), SwapError> {
+    match &cli.require_lock {
+        Some(lock) if lock.symlink_metadata().is_err() => Err(SwapError::MissingDeployLock(lock.clone())),
+        Some(lock) => {
+            log!(cli, "Deploy lock '{}' is present.", lock.display());
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Implements `--consume-lock`, once the swap succeeded. The swap is done by then, so a lock
+/// that cannot be removed is only reported as a warning.
+fn consume_deploy_lock(cli: &Cli) {
+    let Some(lock) = cli.require_lock.as_ref().filter(|_| cli.consume_lock && !cli.dry_run) else { return };
+    match fs::remove_file(lock) {
+        Ok(()) => log!(cli, "Removed the deploy lock '{}'.", lock.display()),
+        Err(e) => warn(format!("could not remove the deploy lock '{}': {}", lock.display(), e)),
+    }
+}
+
+/// Warns in verbose mode when an item is on a FUSE filesystem, whose renames may be neither
+/// atomic nor complete, and refuses the swap under `--strict`.
+fn check_fuse(paths: &[&Path], cli: &Cli) -> Result<(), SwapError> {
+    for path in paths {
+        let Some(parent) = path.parent() else { continue };
+        if sys::fs_type(parent).ok() != Some(sys::FUSE_SUPER_MAGIC) {
+            continue;
+        }
+        if cli.strict {
+            return Err(SwapError::FuseUnreliable(path.to_path_buf()));
+        }
+        log!(cli, "Warning: '{}' is on a FUSE filesystem, the swap may not be atomic.", path.display());
+    }
+    Ok(())
+}
+
+/// Refuses a `--chown` the process could not apply, before anything is moved: without root
+/// privileges, only the own user and the groups the process belongs to can be given.
+fn check_chown_permitted(cli: &Cli) -> Result<(), SwapError> {
+    let Some(owner) = &cli.chown else {
+        return Ok(());
+    };
+    let euid = sys::effective_uid();
+    let permitted = euid == 0
+        || (owner.uid.is_none_or(|uid| uid == euid) && owner.gid.is_none_or(sys::in_group));
+    if !permitted {
+        return Err(SwapError::ChownNotPermitted(owner.spec.clone()));
+    }
+    Ok(())
+}
+
+/// Applies `--chown` to `path` and, for a directory, to everything below it.
+/// Symlinks themselves are changed, never their targets.
+#[cfg(unix)]
+fn change_owner(path: &Path, owner: &Owner) -> Result<(), SwapError> {
+    let io_err = |e: std::io::Error| match e.raw_os_error() {
+        Some(libc::EPERM) => SwapError::ChownNotPermitted(owner.spec.clone()),
+        _ => SwapError::Io(e, path.to_path_buf()),
+    };
+    std::os::unix::fs::lchown(path, owner.uid, owner.gid).map_err(io_err)?;
+
+    if path.symlink_metadata().map_err(io_err)?.is_dir() {
+        for entry in fs::read_dir(path).map_err(io_err)? {
+            change_owner(&entry.map_err(io_err)?.path(), owner)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn change_owner(path: &Path, _owner: &Owner) -> Result<(), SwapError> {
+    Err(SwapError::Io(std::io::Error::from(std::io::ErrorKind::Unsupported), path.to_path_buf()))
+}
+
+/// Checks up front that every path exists and that their directories are writable, reporting every
+/// problem found rather than the first one. A single problem is returned as is.
+/// Paths after the second one, which only rotations have, are resolved like the second.
+fn preflight(args: &[&Path], cli: &Cli) -> Result<(), SwapError> {
+    let mut problems = Vec::new();
+    let mut unwritable: Vec<PathBuf> = Vec::new();
+    for (index, arg) in args.iter().enumerate() {
+        let side = if index == 0 { 1 } else { 2 };
+        let resolved = if cli.dereferences(side) { fs::canonicalize(arg) } else { absolute_lexical(arg) };
+        let path = match resolved {
+            Ok(path) => path,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                problems.push(SwapError::PathNotFound(arg.to_path_buf()));
+                continue;
+            }
+            Err(e) => {
+                problems.push(SwapError::Io(e, arg.to_path_buf()));
+                continue;
+            }
+        };
+        // An item designated with a trailing slash may be an entry of that directory instead,
+        // only known once resolved; `--attributes-only` renames nothing.
+        if has_trailing_separator(arg) || cli.attributes_only {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            if !sys::is_writable(parent) && !unwritable.iter().any(|dir| dir == parent) {
+                unwritable.push(parent.to_path_buf());
+                problems.push(SwapError::ParentNotWritable(parent.to_path_buf()));
+            }
+        }
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err(problems.remove(0)),
+        _ => Err(SwapError::PreflightFailed(problems)),
+    }
+}
+
+/// Enforces `--require-same-type` and `--require-type` on the paths as they were given,
+/// so that a symlink argument counts as a symlink even though its target is what gets swapped.
+/// `--require-same-type` expects every path to be of the type of the first one.
+fn check_required_types(args: &[&Path], cli: &Cli) -> Result<(), SwapError> {
+    if !cli.require_same_type && cli.require_type.is_none() {
+        return Ok(());
+    }
+
+    let kind = |path: &Path| {
+        path.symlink_metadata()
+            .map(|metadata| FileKind::of(&metadata))
+            .map_err(|e| SwapError::Io(e, path.to_path_buf()))
+    };
+    let kinds = args.iter().map(|arg| kind(arg)).collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(expected) = cli.require_type {
+        for (path, &found) in args.iter().zip(&kinds) {
+            if found != expected {
+                return Err(SwapError::TypeMismatch { path: path.to_path_buf(), expected, found });
+            }
+        }
+    }
+    if cli.require_same_type {
+        for (path, &found) in args.iter().zip(&kinds).skip(1) {
+            if found != kinds[0] {
+                return Err(SwapError::TypeMismatch { path: path.to_path_buf(), expected: kinds[0], found });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Refuses the swap if it would leave less than `--reserve` bytes available on any involved filesystem.
+/// `moves` pairs every item with the directory it ends up in. Items only consume data space when
+/// they move to another filesystem; same-filesystem renames only need the reserve itself to be available.
+fn check_free_space_reserve(moves: &[(&Path, &Path)], cli: &Cli) -> Result<(), SwapError> {
+    let Some(reserve) = cli.reserve else {
+        return Ok(());
+    };
+
+    let device = |path: &Path| sys::device_id(path).map_err(|e| SwapError::Io(e, path.to_path_buf()));
+    for &(incoming_item, fs_path) in moves {
+        let from = incoming_item.parent().ok_or_else(|| SwapError::MissingParent(incoming_item.to_path_buf()))?;
+        let available = sys::available_space(fs_path).map_err(|e| SwapError::Io(e, fs_path.to_path_buf()))?;
+        let incoming = if device(from)? != device(fs_path)? { tree_size(incoming_item)? } else { 0 };
+        let remaining = available.saturating_sub(incoming);
+
+        log!(cli, "Free space on '{}': {} (after swap: {})", fs_path.display(), format_size(available), format_size(remaining));
+        if remaining < reserve {
+            return Err(SwapError::WouldExceedReserve { fs: fs_path.to_path_buf(), remaining });
+        }
+    }
+    Ok(())
+}
+
+/// Refuses the swap if the items hold more than `--max-entries` entries, counting each item,
+/// and everything below it for a directory. Symlinks are not followed.
+fn check_entry_limit(paths: &[&Path], cli: &Cli) -> Result<(), SwapError> {
+    let Some(limit) = cli.max_entries else {
+        return Ok(());
+    };
+    let mut count = 0;
+    for path in paths {
+        if !count_entries(path, &mut count, limit)? {
+            return Err(SwapError::TooManyEntries { count, limit });
+        }
+    }
+    log!(cli, "The swap involves {} entries (limit: {})", count, limit);
+    Ok(())
+}
+
+/// Adds to `count` the entry at `path` and the ones below it, stopping as soon as the count
+/// exceeds `limit`. Returns whether it stayed within the limit.
+fn count_entries(path: &Path, count: &mut u64, limit: u64) -> Result<bool, SwapError> {
+    *count += 1;
+    if *count > limit {
+        return Ok(false);
+    }
+    let metadata = path.symlink_metadata().map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).map_err(|e| SwapError::Io(e, path.to_path_buf()))? {
+            let entry = entry.map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+            if !count_entries(&entry.path(), count, limit)? {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Implements `--smoke-test` on the entry at `path`: reads the entries of a directory and
+/// recurses into them, and reads the first and last byte of a file, which must both be there.
+fn smoke_test(path: &Path, cli: &Cli) -> Result<(), SwapError> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let failed = |e: io::Error| {
+        log!(cli, "Smoke test of '{}' failed: {}", path.display(), e);
+        SwapError::SmokeTestFailed(path.to_path_buf())
+    };
+    let metadata = path.symlink_metadata().map_err(failed)?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).map_err(failed)? {
+            smoke_test(&entry.map_err(failed)?.path(), cli)?;
+        }
+    } else if metadata.is_file() && metadata.len() > 0 {
+        let mut file = fs::File::open(path).map_err(failed)?;
+        let mut byte = [0u8; 1];
+        file.read_exact(&mut byte).map_err(failed)?;
+        file.seek(SeekFrom::Start(metadata.len() - 1)).map_err(failed)?;
+        file.read_exact(&mut byte).map_err(failed)?;
+    }
+    Ok(())
+}
+
+/// Implements `--suggest-cd`: if `cwd` was inside an item of the `(from, to)` moves, prints the
+/// `cd` command to the same place under its new location.
+fn suggest_cd(cwd: &Path, moves: &[(&Path, &Path)]) {
+    for (from, to) in moves {
+        if let Ok(rest) = cwd.strip_prefix(from) {
+            let target = to.join(rest);
+            if target != cwd {
+                eprintln!("Your current directory moved with the swap. To follow it: cd {}", shell_quote(&target));
+            }
+            return;
+        }
+    }
+}
+
+/// Quotes `path` for a POSIX shell.
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// Hashes the whole entry at `path` for `--verify`, counting the bytes read.
+fn content_digest(path: &Path) -> Result<[u8; 32], SwapError> {
+    let digest = hash::tree_sha256(path).map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+    metrics::add(&metrics::BYTES_VERIFIED, tree_size(path)?);
+    Ok(digest)
+}
+
+/// Returns the total size in bytes of a file, or of every file below a directory. Symlinks are not followed.
+fn tree_size(path: &Path) -> Result<u64, SwapError> {
+    let metadata = path.symlink_metadata().map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut total = 0;
+    for entry in fs::read_dir(path).map_err(|e| SwapError::Io(e, path.to_path_buf()))? {
+        let entry = entry.map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+        total += tree_size(&entry.path())?;
+    }
+    Ok(total)
+}
+
+/// Parses a `--prefix-map` rule such as `/mnt/old=/srv/new`.
+fn parse_prefix_rule(input: &str) -> Result<PrefixRule, String> {
+    match input.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => Ok(PrefixRule { old: PathBuf::from(old), new: PathBuf::from(new) }),
+        _ => Err(format!("'{}' is not of the form OLD=NEW with two non-empty prefixes", input)),
+    }
+}
+
+/// Parses an octal umask such as `022` or `0027`.
+fn parse_umask(input: &str) -> Result<u32, String> {
+    match u32::from_str_radix(input, 8) {
+        Ok(mask) if mask <= 0o777 => Ok(mask),
+        _ => Err(format!("'{}' is not an octal umask between 000 and 777", input)),
+    }
+}
+
+/// Parses a human-readable size such as `4096`, `500K`, `20M` or `1.5G` (binary units).
+fn parse_size(input: &str) -> Result<u64, String> {
+    let trimmed = input.trim();
+    let trimmed = trimmed.strip_suffix("iB").or_else(|| trimmed.strip_suffix('B')).unwrap_or(trimmed);
+    let (number, multiplier) = match trimmed.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&trimmed[..trimmed.len() - 1], 1u64 << 10),
+        Some('M') => (&trimmed[..trimmed.len() - 1], 1u64 << 20),
+        Some('G') => (&trimmed[..trimmed.len() - 1], 1u64 << 30),
+        Some('T') => (&trimmed[..trimmed.len() - 1], 1u64 << 40),
+        _ => (trimmed, 1),
+    };
+
+    let value: f64 = number.trim().parse().map_err(|_| format!("invalid size '{}'", input))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("invalid size '{}'", input));
+    }
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Parses the `USER[:GROUP]` of `--chown`, resolving names to numeric ids. `USER:` takes the
+/// login group of the user, and `:GROUP` only changes the group.
+fn parse_owner(input: &str) -> Result<Owner, String> {
+    let (user, group) = match input.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (input, None),
+    };
+
+    let mut login_gid = None;
+    let uid = match user {
+        "" => None,
+        user if user.bytes().all(|b| b.is_ascii_digit()) => Some(user.parse().map_err(|_| format!("invalid user id '{}'", user))?),
+        user => {
+            let (uid, gid) = sys::lookup_user(user)
+                .map_err(|e| format!("cannot look up user '{}': {}", user, e))?
+                .ok_or_else(|| format!("no such user '{}'", user))?;
+            login_gid = Some(gid);
+            Some(uid)
+        }
+    };
+    let gid = match group {
+        None => None,
+        Some("") if user.is_empty() => return Err("expected USER, USER:GROUP, USER: or :GROUP".to_string()),
+        Some("") => Some(login_gid.ok_or_else(|| format!("'{}:' needs a user name, not an id", user))?),
+        Some(group) if group.bytes().all(|b| b.is_ascii_digit()) => Some(group.parse().map_err(|_| format!("invalid group id '{}'", group))?),
+        Some(group) => Some(
+            sys::lookup_group(group)
+                .map_err(|e| format!("cannot look up group '{}': {}", group, e))?
+                .ok_or_else(|| format!("no such group '{}'", group))?,
+        ),
+    };
+    if uid.is_none() && gid.is_none() {
+        return Err("expected USER, USER:GROUP, USER: or :GROUP".to_string());
+    }
+    Ok(Owner { spec: input.to_string(), uid, gid })
+}
+
+/// Formats a byte count with the largest fitting binary unit, e.g. `1.5 GiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Lowercases a name, replaces whitespace with underscores and drops every character
+/// that is not alphanumeric, `.`, `-` or `_`. Repeated underscores are collapsed.
+fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars().flat_map(char::to_lowercase) {
+        let mapped = match c {
+            c if c.is_whitespace() => '_',
+            c if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' => c,
+            _ => continue,
+        };
+        if mapped == '_' && slug.ends_with('_') {
+            continue;
+        }
+        slug.push(mapped);
+    }
+    slug
+}
+
+/// Guards against collisions introduced by name normalization.
+/// A destination is only allowed to exist if it is one of the two paths being vacated by the swap.
+fn check_normalization_collisions(path1: &Path, path2: &Path, dest1: &Path, dest2: &Path, cli: &Cli) -> Result<(), SwapError> {
+    if cli.normalize_mode().is_none() {
+        return Ok(());
+    }
+
+    if dest1 == dest2 {
+        return Err(SwapError::NormalizationCollision(dest1.to_path_buf()));
+    }
+    for dest in [dest1, dest2] {
+        let vacated = dest == path1 || dest == path2;
+        // A taken destination is moved out of the way by `--quarantine`.
+        if !vacated && cli.quarantine.is_none() && dest.symlink_metadata().is_ok() {
+            return Err(SwapError::NormalizationCollision(dest.to_path_buf()));
+        }
+    }
+
+    log!(cli, "Normalized destinations: '{}' and '{}'", dest1.display(), dest2.display());
+    Ok(())
+}
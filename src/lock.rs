@@ -0,0 +1,107 @@
+//! `--lock-dir`: serializes concurrent swaps with lock directories, where `RENAME_EXCHANGE` is
+//! not available to make a swap atomic.
+//!
+//! `mkdir` is atomic on every filesystem, network ones included, and fails if the directory
+//! exists. Each parent directory involved gets a `.swap.lock` directory for the duration of the
+//! swap, holding a note about its owner. Parents are locked in path order, so two swaps locking
+//! the same directories cannot deadlock. A swap that finds a lock waits for it to be released.
+//! A process killed while holding a lock leaves it behind: `swap recover-locks` removes the
+//! locks older than a given age.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{iso_timestamp, Cli, SwapError};
+
+/// Name of the lock directory created in each locked parent.
+pub const LOCK_NAME: &str = ".swap.lock";
+
+/// How long a swap waits for a lock before giving up.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A held lock, released when dropped.
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Locks every directory of `dirs`, each once and in path order. Returns the held locks.
+pub fn acquire(dirs: &[&Path], cli: &Cli) -> Result<Vec<Lock>, SwapError> {
+    let mut dirs = dirs.to_vec();
+    dirs.sort();
+    dirs.dedup();
+
+    let mut locks = Vec::with_capacity(dirs.len());
+    for dir in dirs {
+        locks.push(lock(dir, cli)?);
+    }
+    Ok(locks)
+}
+
+fn lock(dir: &Path, cli: &Cli) -> Result<Lock, SwapError> {
+    let path = dir.join(LOCK_NAME);
+    let started = std::time::Instant::now();
+    let mut waiting = false;
+    loop {
+        match fs::create_dir(&path) {
+            Ok(()) => break,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if started.elapsed() >= WAIT_TIMEOUT {
+                    return Err(SwapError::LockBusy(path));
+                }
+                if !waiting {
+                    log!(cli, "Waiting for the lock '{}'...", path.display());
+                    waiting = true;
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => return Err(SwapError::Io(e, path)),
+        }
+    }
+
+    // From here on, the guard removes the lock whatever happens.
+    let lock = Lock { path };
+    let owner = format!("pid {} since {}\n", std::process::id(), iso_timestamp(SystemTime::now()));
+    fs::write(lock.path.join("owner"), owner).map_err(|e| SwapError::Io(e, lock.path.clone()))?;
+    log!(cli, "Locked '{}'", dir.display());
+    Ok(lock)
+}
+
+/// Implements `swap recover-locks`: removes the lock directories of `dirs` older than `stale_after`.
+pub fn recover(dirs: &[PathBuf], stale_after: Duration) -> Result<(), SwapError> {
+    for dir in dirs {
+        let path = dir.join(LOCK_NAME);
+        let metadata = match path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                println!("'{}' is not locked.", dir.display());
+                continue;
+            }
+            Err(e) => return Err(SwapError::Io(e, path)),
+        };
+        let owner = match fs::read_to_string(path.join("owner")) {
+            Ok(owner) if !owner.trim().is_empty() => format!(" by {}", owner.trim()),
+            _ => String::new(),
+        };
+        let age = metadata.modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+
+        if age < stale_after {
+            println!("Kept the lock of '{}', held for {}s{}.", dir.display(), age.as_secs(), owner);
+            continue;
+        }
+        fs::remove_dir_all(&path).map_err(|e| SwapError::Io(e, path.clone()))?;
+        println!("Removed the stale lock of '{}', held for {}s{}.", dir.display(), age.as_secs(), owner);
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! `--from-file` manifests: a batch of swaps, one pair of paths per line.
+//!
+//! The two paths of a line are separated by a tab, or by whitespace when neither contains any.
+//! Empty lines and lines starting with `#` are ignored. The first line may be a header setting
+//! options for the whole batch:
+//!
+//! ```text
+//! # swap-options: mode=name normalize=slug strict=yes
+//! ```
+//!
+//! Supported keys are `mode` (`location` or `name`), `normalize` (`lowercase` or `slug`), and the
+//! switches `no-dereference`, `strict`, `best-effort`, `concurrency-safe`, `annotate` and `expand`
+//! (`yes` or `no`). Flags given on the command line take precedence. Unknown keys are ignored with a warning.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{warn, Cli, NormalizeMode, SwapError};
+
+/// Prefix of the optional header line.
+const HEADER_PREFIX: &str = "# swap-options:";
+
+/// Reads the manifest at `path`, applies its header to `cli`, and returns the pairs to swap.
+pub fn read(path: &Path, cli: &mut Cli) -> Result<Vec<(PathBuf, PathBuf)>, SwapError> {
+    let content = fs::read_to_string(path).map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+    let invalid = |line: usize, reason: String| SwapError::InvalidManifest { path: path.to_path_buf(), line, reason };
+
+    let mut pairs = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let number = index + 1;
+        let trimmed = line.trim();
+
+        if index == 0 {
+            if let Some(options) = trimmed.strip_prefix(HEADER_PREFIX) {
+                apply_header(options, cli).map_err(|reason| invalid(number, reason))?;
+                continue;
+            }
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let pair = parse_pair(line).ok_or_else(|| invalid(number, "expected two paths".to_string()))?;
+        pairs.push(pair);
+    }
+    Ok(pairs)
+}
+
+/// Splits a line into its two paths: on the tab if there is one, otherwise on whitespace.
+fn parse_pair(line: &str) -> Option<(PathBuf, PathBuf)> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let (first, second) = if let Some((first, second)) = line.split_once('\t') {
+        (first.trim(), second.trim())
+    } else {
+        let mut words = line.split_whitespace();
+        let pair = (words.next()?, words.next()?);
+        if words.next().is_some() {
+            return None;
+        }
+        pair
+    };
+
+    if first.is_empty() || second.is_empty() || second.contains('\t') {
+        return None;
+    }
+    Some((PathBuf::from(first), PathBuf::from(second)))
+}
+
+/// Applies the `key=value` options of the header to `cli`, leaving the flags set on the command line alone.
+fn apply_header(options: &str, cli: &mut Cli) -> Result<(), String> {
+    for option in options.split_whitespace() {
+        let Some((key, value)) = option.split_once('=') else {
+            return Err(format!("expected key=value in the header, found '{}'", option));
+        };
+
+        let switch = || match value {
+            "yes" | "true" => Ok(true),
+            "no" | "false" => Ok(false),
+            _ => Err(format!("'{}' expects yes or no, found '{}'", key, value)),
+        };
+        match key {
+            "mode" => match value {
+                "name" => cli.name_swap = true,
+                "location" => {}
+                _ => return Err(format!("'mode' expects location or name, found '{}'", value)),
+            },
+            "normalize" => {
+                let mode = match value {
+                    "lowercase" => NormalizeMode::Lowercase,
+                    "slug" => NormalizeMode::Slug,
+                    _ => return Err(format!("'normalize' expects lowercase or slug, found '{}'", value)),
+                };
+                if cli.normalize_mode().is_none() {
+                    cli.normalize = Some(mode);
+                }
+            }
+            "no-dereference" => cli.no_dereference |= switch()?,
+            "strict" => cli.strict |= switch()?,
+            "best-effort" => cli.best_effort |= switch()?,
+            "concurrency-safe" => cli.concurrency_safe |= switch()?,
+            "annotate" => cli.annotate |= switch()?,
+            "expand" => cli.expand |= switch()?,
+            _ => warn(format!("ignoring unknown manifest option '{}'.", key)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    /// The command line `swap ARGS x y`.
+    fn cli(args: &[&str]) -> Cli {
+        Cli::parse_from(["swap"].iter().chain(args).chain(&["x", "y"]))
+    }
+
+    fn pair(first: &str, second: &str) -> Option<(PathBuf, PathBuf)> {
+        Some((PathBuf::from(first), PathBuf::from(second)))
+    }
+
+    /// Reads a manifest with `content` from a temporary file.
+    fn read_manifest(content: &str, cli: &mut Cli) -> Result<Vec<(PathBuf, PathBuf)>, SwapError> {
+        let path = std::env::temp_dir().join(format!("swap-manifest-{}", uuid::Uuid::new_v4()));
+        fs::write(&path, content).unwrap();
+        let pairs = read(&path, cli);
+        fs::remove_file(&path).unwrap();
+        pairs
+    }
+
+    #[test]
+    fn a_tab_separates_paths_holding_spaces() {
+        assert_eq!(parse_pair("my file.txt\tother file.txt"), pair("my file.txt", "other file.txt"));
+        assert_eq!(parse_pair("  a \t b  \r\n"), pair("a", "b"));
+        assert_eq!(parse_pair("a\tb\tc"), None);
+        assert_eq!(parse_pair("a\t"), None);
+    }
+
+    #[test]
+    fn without_a_tab_whitespace_separates_exactly_two_paths() {
+        assert_eq!(parse_pair("a/x   b/y"), pair("a/x", "b/y"));
+        assert_eq!(parse_pair("a b c"), None);
+        assert_eq!(parse_pair("alone"), None);
+    }
+
+    #[test]
+    fn the_header_is_only_read_on_the_first_line() {
+        let mut first = cli(&[]);
+        let pairs = read_manifest("# swap-options: mode=name strict=yes\na b\n", &mut first).unwrap();
+        assert_eq!(pairs, [(PathBuf::from("a"), PathBuf::from("b"))]);
+        assert!(first.name_swap && first.strict);
+
+        let mut later = cli(&[]);
+        let pairs = read_manifest("a b\n# swap-options: mode=name strict=yes\n", &mut later).unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert!(!later.name_swap && !later.strict);
+    }
+
+    #[test]
+    fn command_line_flags_take_precedence() {
+        let mut cli = cli(&["--name-swap", "--strict", "--normalize", "lowercase"]);
+        apply_header("mode=location strict=no normalize=slug best-effort=yes", &mut cli).unwrap();
+        assert!(cli.name_swap && cli.strict && cli.best_effort);
+        assert_eq!(cli.normalize_mode(), Some(NormalizeMode::Lowercase));
+    }
+
+    #[test]
+    fn invalid_header_values_are_refused() {
+        assert!(apply_header("strict=maybe", &mut cli(&[])).is_err());
+        assert!(apply_header("mode=sideways", &mut cli(&[])).is_err());
+        assert!(apply_header("strict", &mut cli(&[])).is_err());
+        let refused = read_manifest("# swap-options: strict=maybe\na b\n", &mut cli(&[]));
+        assert!(matches!(refused, Err(SwapError::InvalidManifest { line: 1, .. })));
+    }
+}
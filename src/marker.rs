@@ -0,0 +1,107 @@
+//! `--link-marker`: leaves a marker file next to both swapped items recording their counterpart,
+//! for tools that need to find out later which items were swapped together.
+//!
+//! A marker is a text file named as given, in the directory of each destination. After its
+//! header line, it holds one tab-separated line per item of that directory: the time of the
+//! swap, the item and its counterpart. Both items of a name swap share the same directory, hence
+//! the same marker. A marker left by an earlier swap is updated in place, replacing the line
+//! of an item swapped again; any other file of that name is left alone. Markers are written
+//! to a temporary file first and renamed over the old one, so a reader never sees half of one.
+//! `swap remove-markers` deletes them.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{iso_timestamp, warn, Cli, SwapError};
+
+/// First line of every marker, telling it apart from an unrelated file of the same name.
+const HEADER: &str = "# swap link marker: swapped-at\titem\tcounterpart";
+
+/// Records in the marker of the directory of `item` that it was swapped with `counterpart`.
+/// The swap already happened, so failures are only reported as warnings.
+pub fn record(name: &str, item: &Path, counterpart: &Path, cli: &Cli) {
+    let Some(dir) = item.parent() else { return };
+    let path = dir.join(name);
+    if path == item || path == counterpart {
+        warn(format!("not writing the link marker '{}', it is one of the swapped items.", path.display()));
+        return;
+    }
+    match update(&path, item, counterpart) {
+        Ok(true) => log!(cli, "Recorded the counterpart of '{}' in '{}'", item.display(), path.display()),
+        Ok(false) => warn(format!(
+            "not writing the link marker '{}', a file of that name that is not a marker exists.", path.display()
+        )),
+        Err(e) => warn(format!("could not write the link marker '{}': {}", path.display(), e)),
+    }
+}
+
+/// Adds the line of `item` to the marker at `path`, replacing its previous one.
+/// Returns false, writing nothing, if `path` exists and is not a marker.
+fn update(path: &Path, item: &Path, counterpart: &Path) -> io::Result<bool> {
+    let mut lines = match read(path)? {
+        Some(lines) => lines,
+        None if path.symlink_metadata().is_ok() => return Ok(false),
+        None => Vec::new(),
+    };
+    let item_field = item.to_string_lossy();
+    lines.retain(|line| line.split('\t').nth(1) != Some(&*item_field));
+    lines.push(format!("{}\t{}\t{}", iso_timestamp(std::time::SystemTime::now()), item_field, counterpart.to_string_lossy()));
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.swap.{}", name, uuid::Uuid::new_v4()));
+    let mut content = format!("{}\n", HEADER);
+    for line in &lines {
+        content += line;
+        content.push('\n');
+    }
+    fs::write(&temp, content)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })?;
+    Ok(true)
+}
+
+/// Reads the lines of the marker at `path`, without its header. Returns `None` if there is no
+/// file there, or a file that is not a marker.
+fn read(path: &Path) -> io::Result<Option<Vec<String>>> {
+    if !path.symlink_metadata().is_ok_and(|metadata| metadata.is_file()) {
+        return Ok(None);
+    }
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut lines = content.lines();
+    if lines.next() != Some(HEADER) {
+        return Ok(None);
+    }
+    Ok(Some(lines.filter(|line| !line.is_empty()).map(str::to_string).collect()))
+}
+
+/// Implements `swap remove-markers`: deletes the markers named `name` from the given directories.
+pub fn remove(name: &str, dirs: &[PathBuf]) -> Result<(), SwapError> {
+    for dir in dirs {
+        let path = dir.join(name);
+        match read(&path).map_err(|e| SwapError::Io(e, path.clone()))? {
+            Some(lines) => {
+                fs::remove_file(&path).map_err(|e| SwapError::Io(e, path.clone()))?;
+                let plural = if lines.len() == 1 { "" } else { "s" };
+                println!("Removed '{}', which recorded {} swapped item{}.", path.display(), lines.len(), plural);
+            }
+            None if path.symlink_metadata().is_ok() => println!("Kept '{}', which is not a link marker.", path.display()),
+            None => println!("'{}' has no link marker.", dir.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Parses the name given to `--link-marker` and `swap remove-markers`, which must be a plain
+/// file name: the marker goes in the directory of each item.
+pub fn parse_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(format!("'{}' is not a file name", name));
+    }
+    Ok(name.to_string())
+}
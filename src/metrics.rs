@@ -0,0 +1,113 @@
+//! Run metrics written by `--metrics-file` in the Prometheus textfile collector format.
+//!
+//! Counters accumulate across runs: the values already in the file are read back and added to.
+//! The file is replaced atomically, so the collector never reads a partial file.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::{temporary_name, SwapError, TempGuard};
+
+/// Pairs swapped, not counting dry runs.
+pub static SWAPS: AtomicU64 = AtomicU64::new(0);
+/// Bytes written while copying items across filesystems.
+pub static BYTES_COPIED: AtomicU64 = AtomicU64::new(0);
+/// Bytes read to check contents with `--verify`.
+pub static BYTES_VERIFIED: AtomicU64 = AtomicU64::new(0);
+/// Swaps done with a single atomic exchange.
+pub static EXCHANGES: AtomicU64 = AtomicU64::new(0);
+/// Swaps done with three renames through a temporary name.
+pub static RENAME_DANCES: AtomicU64 = AtomicU64::new(0);
+/// Items copied to another filesystem instead of renamed.
+pub static COPIES: AtomicU64 = AtomicU64::new(0);
+/// Atomic exchanges the filesystem refused, done with three renames instead.
+pub static EXCHANGE_REFUSALS: AtomicU64 = AtomicU64::new(0);
+/// Temporary names tried again because another process created them first.
+pub static TEMP_RETRIES: AtomicU64 = AtomicU64::new(0);
+
+/// The counters describing how swaps are done, at some point of the run.
+#[derive(Clone, Copy)]
+pub struct Snapshot {
+    pub exchanges: u64,
+    pub exchange_refusals: u64,
+    pub copies: u64,
+    pub temp_retries: u64,
+}
+
+impl Snapshot {
+    pub fn take() -> Self {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Snapshot { exchanges: load(&EXCHANGES), exchange_refusals: load(&EXCHANGE_REFUSALS), copies: load(&COPIES), temp_retries: load(&TEMP_RETRIES) }
+    }
+
+    /// Returns what was counted between `earlier` and this snapshot.
+    pub fn since(self, earlier: Snapshot) -> Snapshot {
+        Snapshot {
+            exchanges: self.exchanges - earlier.exchanges,
+            exchange_refusals: self.exchange_refusals - earlier.exchange_refusals,
+            copies: self.copies - earlier.copies,
+            temp_retries: self.temp_retries - earlier.temp_retries,
+        }
+    }
+}
+
+/// Adds `value` to one of the counters above.
+pub fn add(counter: &AtomicU64, value: u64) {
+    counter.fetch_add(value, Ordering::Relaxed);
+}
+
+/// Writes the metrics of this run to `path`, on top of the counters already recorded there.
+pub fn write(path: &Path, failed: bool, duration: Duration) -> Result<(), SwapError> {
+    let previous = fs::read_to_string(path).unwrap_or_default();
+    let total = |sample: &str, value: u64| value + previous_value(&previous, sample);
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+    let mut out = String::new();
+    let mut counter = |name: &str, help: &str, samples: &[(&str, u64)]| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (labels, value) in samples {
+            let sample = format!("{}{}", name, labels);
+            let _ = writeln!(out, "{} {}", sample, total(&sample, *value));
+        }
+    };
+    counter("swap_swaps_total", "Pairs swapped successfully.", &[("", load(&SWAPS))]);
+    counter("swap_failures_total", "Runs that failed.", &[("", u64::from(failed))]);
+    counter("swap_bytes_copied_total", "Bytes copied across filesystems.", &[("", load(&BYTES_COPIED))]);
+    counter("swap_bytes_verified_total", "Bytes read to verify contents.", &[("", load(&BYTES_VERIFIED))]);
+    counter("swap_strategy_total", "Swaps and copies by strategy.", &[
+        ("{strategy=\"exchange\"}", load(&EXCHANGES)),
+        ("{strategy=\"rename\"}", load(&RENAME_DANCES)),
+        ("{strategy=\"copy\"}", load(&COPIES)),
+    ]);
+    counter("swap_exchange_refusals_total", "Atomic exchanges refused by the filesystem.", &[("", load(&EXCHANGE_REFUSALS))]);
+    counter("swap_temp_retries_total", "Temporary names retried after a collision.", &[("", load(&TEMP_RETRIES))]);
+
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let _ = writeln!(out, "# HELP swap_last_run_duration_seconds Duration of the last run.");
+    let _ = writeln!(out, "# TYPE swap_last_run_duration_seconds gauge");
+    let _ = writeln!(out, "swap_last_run_duration_seconds {:.6}", duration.as_secs_f64());
+    let _ = writeln!(out, "# HELP swap_last_run_timestamp_seconds Time the last run ended.");
+    let _ = writeln!(out, "# TYPE swap_last_run_timestamp_seconds gauge");
+    let _ = writeln!(out, "swap_last_run_timestamp_seconds {}", timestamp.as_secs());
+
+    // Written next to the target so the final rename stays on the same filesystem.
+    let name = path.file_name().ok_or_else(|| SwapError::MissingParent(path.to_path_buf()))?;
+    let temp = TempGuard::new(path.with_file_name(temporary_name(name)));
+    fs::write(temp.path(), out).map_err(|e| SwapError::Io(e, temp.path().to_path_buf()))?;
+    fs::rename(temp.path(), path).map_err(|e| SwapError::Io(e, path.to_path_buf()))?;
+    temp.commit();
+    Ok(())
+}
+
+/// Returns the value of `sample` in a previously written metrics file, or 0.
+fn previous_value(previous: &str, sample: &str) -> u64 {
+    previous.lines()
+        .filter_map(|line| line.rsplit_once(' '))
+        .find(|(name, _)| *name == sample)
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0)
+}
@@ -0,0 +1,89 @@
+//! `--dry-run --emit-mmv`: the planned moves as input for `mmv`, to review and run them with it.
+//!
+//! `mmv` reads pairs of patterns from stdin, separated by whitespace, and performs them as a
+//! whole: it detects that two items trade places (or that three rotate) and goes through a
+//! temporary name itself. The moves are therefore printed as they are planned, without the
+//! temporary steps of `swap`. In the source pattern, the wildcards `*`, `?`, `[`, `]` and `;`
+//! are escaped with a backslash, as are `#` (which starts a reference in the target pattern)
+//! and the backslash itself. `mmv` has no way to write whitespace in a pattern read from stdin,
+//! so paths holding some are refused.
+
+use std::path::Path;
+
+use crate::plan::Move;
+use crate::SwapError;
+
+/// Prints `moves` in the input format of `mmv`, one pair per line. Items that stay in place are
+/// left out. Nothing is printed if a path cannot be written for `mmv`.
+pub fn print(moves: &[Move]) -> Result<(), SwapError> {
+    for line in lines(moves)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Returns the lines `print` writes for `moves`, or the first path that cannot be written.
+fn lines(moves: &[Move]) -> Result<Vec<String>, SwapError> {
+    moves
+        .iter()
+        .filter(|item| item.from != item.to)
+        .map(|item| Ok(format!("{} {}", escape(&item.from, "*?[];#\\")?, escape(&item.to, "#\\")?)))
+        .collect()
+}
+
+/// Precedes each character of `special` in `path` with a backslash.
+fn escape(path: &Path, special: &str) -> Result<String, SwapError> {
+    let text = path.to_str().ok_or_else(|| SwapError::NotMmvExpressible(path.to_path_buf(), "is not valid UTF-8"))?;
+    if text.chars().any(char::is_whitespace) {
+        return Err(SwapError::NotMmvExpressible(path.to_path_buf(), "contains whitespace"));
+    }
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Ok(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn planned(from: &str, to: &str) -> Move {
+        Move { from: PathBuf::from(from), to: PathBuf::from(to) }
+    }
+
+    #[test]
+    fn wildcards_are_escaped_in_the_source_only() {
+        let lines = lines(&[planned("/srv/a*?[];#\\b", "/srv/c*?[];#\\d")]).unwrap();
+        assert_eq!(lines, ["/srv/a\\*\\?\\[\\]\\;\\#\\\\b /srv/c*?[];\\#\\\\d"]);
+    }
+
+    #[test]
+    fn items_staying_in_place_are_left_out() {
+        let lines = lines(&[planned("/srv/a/x", "/srv/b/x"), planned("/srv/c", "/srv/c"), planned("/srv/b/y", "/srv/a/y")]).unwrap();
+        assert_eq!(lines, ["/srv/a/x /srv/b/x", "/srv/b/y /srv/a/y"]);
+    }
+
+    #[test]
+    fn paths_with_whitespace_are_refused() {
+        for path in ["/srv/a b", "/srv/tab\there", "/srv/new\nline"] {
+            let refused = lines(&[planned("/srv/ok", "/srv/fine"), planned(path, "/srv/x")]);
+            assert!(matches!(refused, Err(SwapError::NotMmvExpressible(_, "contains whitespace"))), "{}", path);
+        }
+        assert!(lines(&[planned("/srv/x", "/srv/a b")]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_that_are_not_utf8_are_refused() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = PathBuf::from(std::ffi::OsStr::from_bytes(b"/srv/\xff"));
+        let refused = lines(&[Move { from: path, to: PathBuf::from("/srv/x") }]);
+        assert!(matches!(refused, Err(SwapError::NotMmvExpressible(_, "is not valid UTF-8"))));
+    }
+}
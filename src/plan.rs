@@ -0,0 +1,239 @@
+//! `--json`: the outcome of a swap, or with `--dry-run` its plan, as a JSON object, and
+//! `swap print-schema`, their JSON Schema.
+//!
+//! The output and the schema are both derived from the types below, with serde and schemars, so
+//! the schema cannot drift from the output. Objects are printed on a single line each: a
+//! `--from-file` run gives JSON Lines. There is no separate `--print-plan` or `--json-lines`
+//! output: the plan is the `--dry-run --json` object, and JSON Lines come from `--from-file`.
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::{Serialize, Serializer};
+
+use crate::RiskLevel;
+
+/// Version of the output format, bumped on incompatible changes.
+const FORMAT_VERSION: u64 = 1;
+
+/// A swap as planned by a dry run.
+#[derive(Serialize, JsonSchema)]
+#[schemars(title = "swap dry-run plan", deny_unknown_fields)]
+pub struct Plan {
+    /// Version of the output format.
+    pub version: Version,
+    /// What the swap exchanges.
+    pub mode: Mode,
+    /// Every item and its destination, in the order given.
+    pub moves: Vec<Move>,
+    /// Entries in the way, moved into --quarantine first.
+    #[serde(serialize_with = "lossy_paths")]
+    pub quarantined: Vec<PathBuf>,
+    /// Whether a failure halfway through could be fully undone.
+    pub rollback_safe: bool,
+    /// Why the rollback could not undo everything.
+    pub rollback_hazards: Vec<String>,
+    /// What could go wrong, as rated for --risk-threshold.
+    pub risks: Vec<Risk>,
+}
+
+/// A swap as it was done, printed by `--json` without `--dry-run`.
+#[derive(Serialize, JsonSchema)]
+#[schemars(title = "swap outcome", deny_unknown_fields)]
+pub struct Outcome {
+    /// Version of the output format.
+    pub version: Version,
+    /// What the swap exchanged.
+    pub mode: Mode,
+    /// Every item and its destination, in the order given.
+    pub moves: Vec<Move>,
+    /// Where the entries in the way went, in --quarantine.
+    #[serde(serialize_with = "lossy_paths")]
+    pub quarantined: Vec<PathBuf>,
+    /// How the items were moved: one atomic exchange, or renames through a temporary name.
+    pub strategy: Strategy,
+    /// Whether the filesystem refused the atomic exchange, leaving the renames.
+    pub exchange_refused: bool,
+    /// Items copied to another filesystem instead of renamed.
+    pub copies: u64,
+    /// Temporary names tried again because another process took them.
+    pub temp_retries: u64,
+    /// Warnings printed during the swap.
+    pub warnings: Vec<String>,
+}
+
+/// One item of the plan and where it goes.
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline, deny_unknown_fields)]
+pub struct Move {
+    /// Canonical path of the item.
+    #[serde(serialize_with = "lossy_path")]
+    pub from: PathBuf,
+    /// Destination of the item.
+    #[serde(serialize_with = "lossy_path")]
+    pub to: PathBuf,
+}
+
+/// A risk of the plan.
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline, deny_unknown_fields)]
+pub struct Risk {
+    /// Severity of the risk.
+    pub level: RiskLevel,
+    /// What the risk is about.
+    pub reason: String,
+}
+
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum Mode {
+    Location,
+    Name,
+    Rotation,
+}
+
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum Strategy {
+    Exchange,
+    Rename,
+}
+
+/// The `version` member, always `FORMAT_VERSION`.
+pub struct Version;
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(FORMAT_VERSION)
+    }
+}
+
+impl JsonSchema for Version {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "Version".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        schemars::json_schema!({ "type": "integer", "const": FORMAT_VERSION })
+    }
+}
+
+impl Plan {
+    pub fn to_json(&self) -> String {
+        to_json(self)
+    }
+}
+
+impl Outcome {
+    pub fn to_json(&self) -> String {
+        to_json(self)
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    // Only strings, numbers and booleans: serializing cannot fail.
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Returns the JSON Schema of the plans printed by `--dry-run --json`.
+pub fn schema() -> String {
+    to_json(&schemars::schema_for!(Plan))
+}
+
+/// Returns the JSON Schema of the outcomes printed by `--json` without `--dry-run`.
+pub fn outcome_schema() -> String {
+    to_json(&schemars::schema_for!(Outcome))
+}
+
+/// Paths that are not valid UTF-8 are written lossily.
+fn lossy_path<S: Serializer>(path: &std::path::Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn lossy_paths<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome() -> Outcome {
+        Outcome {
+            version: Version,
+            mode: Mode::Location,
+            moves: vec![
+                Move { from: PathBuf::from("/srv/a/x"), to: PathBuf::from("/srv/b/x") },
+                Move { from: PathBuf::from("/srv/b/y"), to: PathBuf::from("/srv/a/y") },
+            ],
+            quarantined: Vec::new(),
+            strategy: Strategy::Rename,
+            exchange_refused: true,
+            copies: 1,
+            temp_retries: 2,
+            warnings: vec!["the \"exchange\" was refused".to_string()],
+        }
+    }
+
+    #[test]
+    fn outcome_is_one_json_object_with_every_field_in_order() {
+        assert_eq!(
+            outcome().to_json(),
+            "{\"version\":1,\"mode\":\"location\",\
+             \"moves\":[{\"from\":\"/srv/a/x\",\"to\":\"/srv/b/x\"},{\"from\":\"/srv/b/y\",\"to\":\"/srv/a/y\"}],\
+             \"quarantined\":[],\"strategy\":\"rename\",\"exchange_refused\":true,\"copies\":1,\"temp_retries\":2,\
+             \"warnings\":[\"the \\\"exchange\\\" was refused\"]}"
+        );
+    }
+
+    #[test]
+    fn odd_paths_are_escaped() {
+        let mut outcome = outcome();
+        outcome.moves = vec![Move { from: PathBuf::from("/a/tab\there\n\u{1}"), to: PathBuf::from("/b/back\\slash") }];
+        let json = outcome.to_json();
+        assert!(json.contains("{\"from\":\"/a/tab\\there\\n\\u0001\",\"to\":\"/b/back\\\\slash\"}"), "{}", json);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_that_are_not_utf8_are_written_lossily() {
+        use std::os::unix::ffi::OsStrExt;
+        let mut outcome = outcome();
+        outcome.quarantined = vec![PathBuf::from(std::ffi::OsStr::from_bytes(b"/q/\xff"))];
+        assert!(outcome.to_json().contains("\"quarantined\":[\"/q/\u{fffd}\"]"));
+    }
+
+    #[test]
+    fn outcome_schema_requires_every_field() {
+        let schema = outcome_schema();
+        assert!(schema.starts_with("{\"$schema\":\"https://json-schema.org/draft/2020-12/schema\",\"title\":\"swap outcome\""), "{}", schema);
+        assert!(schema.contains(
+            "\"required\":[\"version\",\"mode\",\"moves\",\"quarantined\",\"strategy\",\"exchange_refused\",\"copies\",\"temp_retries\",\"warnings\"]"
+        ));
+        assert!(schema.contains("\"type\":\"string\",\"enum\":[\"exchange\",\"rename\"]"), "{}", schema);
+    }
+
+    #[test]
+    fn plan_lists_its_rollback_hazards_and_risks() {
+        let plan = Plan {
+            version: Version,
+            mode: Mode::Name,
+            moves: Vec::new(),
+            quarantined: Vec::new(),
+            rollback_safe: false,
+            rollback_hazards: vec!["a copy".to_string()],
+            risks: vec![Risk { level: RiskLevel::High, reason: "overwrite".to_string() }],
+        };
+        let json = plan.to_json();
+        assert!(json.starts_with("{\"version\":1,\"mode\":\"name\","), "{}", json);
+        assert!(json.contains("\"rollback_safe\":false,\"rollback_hazards\":[\"a copy\"]"), "{}", json);
+        assert!(json.contains("\"risks\":[{\"level\":\"high\",\"reason\":\"overwrite\"}]"), "{}", json);
+    }
+}
@@ -0,0 +1,184 @@
+//! `--relativize-targets`: after swapping symlinks themselves (with `--no-dereference`), rewrites
+//! their targets as paths relative to their new location, so they keep pointing at the same
+//! entries.
+//!
+//! A relative target means something else once its link has moved, and an absolute one ties a
+//! symlink farm to where it lives. Each target is resolved lexically from the old location of its
+//! link; a target inside one of the swapped items follows that item to its destination. Targets
+//! inside the directory shared by both items are then written relative to the new location of the
+//! link. Absolute targets outside of it are left unchanged, as are relative ones that would
+//! come out the same. A link is rewritten by creating the new link under a temporary name and
+//! renaming it over the old one, so it never goes missing.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{temporary_name, Cli, SwapError, TempGuard};
+
+/// A swapped symlink whose target is to be rewritten.
+pub struct Relink {
+    /// Where the link is after the swap.
+    pub link: PathBuf,
+    pub old_target: PathBuf,
+    pub new_target: PathBuf,
+}
+
+/// Plans the links to rewrite among the `(item, destination)` moves of a swap, before it is done.
+pub fn plan(moves: &[(&Path, &Path)]) -> Result<Vec<Relink>, SwapError> {
+    let mut relinks = Vec::new();
+    for (item, dest) in moves {
+        if !item.symlink_metadata().is_ok_and(|metadata| metadata.is_symlink()) {
+            continue;
+        }
+        let old_target = fs::read_link(item).map_err(|e| SwapError::Io(e, item.to_path_buf()))?;
+        if let Some(new_target) = new_target(item, dest, &old_target, moves) {
+            relinks.push(Relink { link: dest.to_path_buf(), old_target, new_target });
+        }
+    }
+    Ok(relinks)
+}
+
+/// Returns the target to give the link `item`, pointing to `old_target`, once it moved to `dest`
+/// with the other `moves` of the swap, or `None` when its target stays as it is.
+fn new_target(item: &Path, dest: &Path, old_target: &Path, moves: &[(&Path, &Path)]) -> Option<PathBuf> {
+    let (parent, dest_parent) = (item.parent()?, dest.parent()?);
+    let mut dirs = moves.iter().flat_map(|(item, dest)| [item, dest]).filter_map(|path| path.parent());
+    let first = dirs.next()?;
+    let shared = dirs.fold(first.to_path_buf(), |shared, dir| common_ancestor(&shared, dir));
+
+    let mut resolved = normalize(&parent.join(old_target));
+    if let Some((from, to)) = moves.iter().find(|(from, _)| resolved.starts_with(from)) {
+        resolved = to.join(resolved.strip_prefix(from).unwrap_or(Path::new("")));
+    }
+    if old_target.is_absolute() && !resolved.starts_with(&shared) {
+        return None;
+    }
+    let new_target = relative_path(dest_parent, &resolved);
+    (new_target != old_target).then_some(new_target)
+}
+
+/// Rewrites the planned links, once the swap is done. The new links keep the owner of the old ones.
+#[cfg(unix)]
+pub fn apply(relinks: &[Relink], cli: &Cli) -> Result<(), SwapError> {
+    for relink in relinks {
+        let io_err = |e| SwapError::Io(e, relink.link.clone());
+        let name = relink.link.file_name().ok_or_else(|| SwapError::MissingParent(relink.link.clone()))?;
+        let metadata = relink.link.symlink_metadata().map_err(io_err)?;
+        let temp = TempGuard::new(relink.link.with_file_name(temporary_name(name)));
+        std::os::unix::fs::symlink(&relink.new_target, temp.path()).map_err(io_err)?;
+        preserve_owner(temp.path(), &metadata).map_err(io_err)?;
+        fs::rename(temp.path(), &relink.link).map_err(io_err)?;
+        temp.commit();
+        log!(cli, "Rewrote the target of '{}': '{}' -> '{}'", relink.link.display(), relink.old_target.display(), relink.new_target.display());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply(relinks: &[Relink], _cli: &Cli) -> Result<(), SwapError> {
+    match relinks.first() {
+        Some(relink) => Err(SwapError::Io(std::io::Error::from(std::io::ErrorKind::Unsupported), relink.link.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Gives the link at `path` the owner of the link described by `metadata`, where it differs:
+/// a new link belongs to the user running `swap`.
+#[cfg(unix)]
+fn preserve_owner(path: &Path, metadata: &fs::Metadata) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let created = path.symlink_metadata()?;
+    if (created.uid(), created.gid()) == (metadata.uid(), metadata.gid()) {
+        return Ok(());
+    }
+    std::os::unix::fs::lchown(path, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+/// Resolves `.` and `..` in an absolute path without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Returns the deepest directory containing both `a` and `b`.
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.components().zip(b.components()).take_while(|(a, b)| a == b).map(|(component, _)| component).collect()
+}
+
+/// Returns the path leading from the directory `base` to `target`, both absolute and normalized.
+fn relative_path(base: &Path, target: &Path) -> PathBuf {
+    let shared = common_ancestor(base, target).components().count();
+    let mut relative: PathBuf = base.components().skip(shared).map(|_| Component::ParentDir).collect();
+    relative.extend(target.components().skip(shared));
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retarget(item: &str, dest: &str, old_target: &str, moves: &[(&str, &str)]) -> Option<PathBuf> {
+        let moves: Vec<(&Path, &Path)> = moves.iter().map(|(from, to)| (Path::new(*from), Path::new(*to))).collect();
+        new_target(Path::new(item), Path::new(dest), Path::new(old_target), &moves)
+    }
+
+    #[test]
+    fn a_relative_target_is_resolved_from_the_new_location() {
+        let moves = [("/srv/farm/a/link", "/srv/farm/b/deeper/link"), ("/srv/farm/b/deeper/other", "/srv/farm/a/other")];
+        assert_eq!(
+            retarget("/srv/farm/a/link", "/srv/farm/b/deeper/link", "../data/file", &moves),
+            Some(PathBuf::from("../../data/file")),
+        );
+    }
+
+    #[test]
+    fn a_target_inside_a_swapped_item_follows_it() {
+        let moves = [("/srv/farm/a/link", "/srv/farm/b/link"), ("/srv/farm/b/dir", "/srv/farm/a/dir")];
+        assert_eq!(retarget("/srv/farm/a/link", "/srv/farm/b/link", "../b/dir/file", &moves), Some(PathBuf::from("../a/dir/file")));
+    }
+
+    #[test]
+    fn a_target_that_comes_out_the_same_is_left_alone() {
+        let moves = [("/srv/farm/a/link", "/srv/farm/b/link"), ("/srv/farm/b/other", "/srv/farm/a/other")];
+        assert_eq!(retarget("/srv/farm/a/link", "/srv/farm/b/link", "../data/file", &moves), None);
+    }
+
+    #[test]
+    fn an_absolute_target_outside_the_shared_directory_is_left_alone() {
+        let moves = [("/srv/farm/a/link", "/srv/farm/b/link"), ("/srv/farm/b/other", "/srv/farm/a/other")];
+        assert_eq!(retarget("/srv/farm/a/link", "/srv/farm/b/link", "/etc/hosts", &moves), None);
+    }
+
+    #[test]
+    fn an_absolute_target_inside_the_shared_directory_is_made_relative() {
+        let moves = [("/srv/farm/a/link", "/srv/farm/b/link"), ("/srv/farm/b/other", "/srv/farm/a/other")];
+        assert_eq!(retarget("/srv/farm/a/link", "/srv/farm/b/link", "/srv/farm/data/file", &moves), Some(PathBuf::from("../data/file")));
+    }
+
+    #[test]
+    fn a_target_in_the_new_directory_of_the_link_is_a_plain_name() {
+        let moves = [("/srv/a/link", "/srv/b/link"), ("/srv/b/other", "/srv/a/other")];
+        assert_eq!(retarget("/srv/a/link", "/srv/b/link", "../b/file", &moves), Some(PathBuf::from("file")));
+        assert_eq!(retarget("/srv/a/link", "/srv/b/link", "../b", &moves), Some(PathBuf::from(".")));
+    }
+
+    #[test]
+    fn paths_are_normalized_lexically() {
+        assert_eq!(normalize(Path::new("/a/./b/../c/")), PathBuf::from("/a/c"));
+        assert_eq!(normalize(Path::new("/../a")), PathBuf::from("/a"));
+        assert_eq!(common_ancestor(Path::new("/a/b/c"), Path::new("/a/bc/d")), PathBuf::from("/a"));
+        assert_eq!(relative_path(Path::new("/a/b/c"), Path::new("/a/d")), PathBuf::from("../../d"));
+    }
+}
@@ -0,0 +1,111 @@
+//! Risk assessment of a planned swap, shared by the dry-run rollback report and by the
+//! confirmation required above `--risk-threshold`.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{copied_items, sys, Cli, PathPair, RiskLevel, SwapError};
+
+/// Something about a planned swap that could surprise the user or cost them data.
+pub struct Risk {
+    pub level: RiskLevel,
+    pub reason: String,
+    /// Whether the rollback of a failed swap could not undo it.
+    pub breaks_rollback: bool,
+}
+
+impl Risk {
+    fn new(level: RiskLevel, reason: String) -> Self {
+        Risk { level, reason, breaks_rollback: false }
+    }
+
+    fn breaking_rollback(mut self) -> Self {
+        self.breaks_rollback = true;
+        self
+    }
+}
+
+/// Lists the risks of swapping `pair` to `dest1` and `dest2`, where `in_the_way` are the
+/// existing entries the swap would replace.
+pub fn assess(pair: &PathPair, dest1: &Path, dest2: &Path, in_the_way: &[&Path], cli: &Cli) -> Result<Vec<Risk>, SwapError> {
+    let mut risks = Vec::new();
+
+    for dest in in_the_way {
+        risks.push(match &cli.quarantine {
+            Some(_) => Risk::new(RiskLevel::Low, format!("'{}' would be quarantined and is not moved back", dest.display())),
+            None => Risk::new(RiskLevel::High, format!("the existing destination '{}' would be overwritten", dest.display())),
+        }.breaking_rollback());
+    }
+
+    let copied = copied_items(pair, dest1, dest2)?;
+    if cli.best_effort && copied.iter().any(|(path, _)| path.is_dir()) {
+        let reason = "--best-effort may leave entries behind in a leftovers directory".to_string();
+        risks.push(Risk::new(RiskLevel::Medium, reason).breaking_rollback());
+    }
+    for (path, parent) in copied {
+        risks.push(Risk::new(RiskLevel::Medium, format!("'{}' would be copied to another filesystem ('{}')", path.display(), parent.display())));
+    }
+
+    for path in [pair.path1, pair.path2] {
+        if is_protected(path) {
+            risks.push(Risk::new(RiskLevel::High, format!("'{}' is a system or home directory", path.display())));
+        } else if is_mount_point(path) {
+            risks.push(Risk::new(RiskLevel::High, format!("'{}' is a mount point", path.display())));
+        }
+    }
+    Ok(risks)
+}
+
+/// Returns the reasons of the risks that reach `--risk-threshold`, with their level.
+pub fn above_threshold(risks: &[Risk], cli: &Cli) -> Vec<String> {
+    risks.iter()
+        .filter(|risk| risk.level >= cli.risk_threshold)
+        .map(|risk| format!("{} ({})", risk.reason, risk.level))
+        .collect()
+}
+
+/// Asks for confirmation when a risk reaches `--risk-threshold`. `--yes` confirms up front;
+/// without a terminal to ask on, the swap is refused.
+pub fn confirm(risks: &[Risk], cli: &Cli) -> Result<(), SwapError> {
+    let reasons = above_threshold(risks, cli);
+    if reasons.is_empty() {
+        return Ok(());
+    }
+    if cli.yes {
+        log!(cli, "Going on despite: {} (--yes).", reasons.join("; "));
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(SwapError::ConfirmationRequired(reasons));
+    }
+
+    eprintln!("This swap is risky:");
+    for reason in &reasons {
+        eprintln!("  - {}", reason);
+    }
+    eprint!("Proceed? [y/N] ");
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).map_err(|e| SwapError::Io(e, PathBuf::from("<stdin>")))?;
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        Ok(())
+    } else {
+        Err(SwapError::NotConfirmed)
+    }
+}
+
+/// Returns true for the root, its direct children (`/etc`, `/usr`, ...) and the home directory.
+fn is_protected(path: &Path) -> bool {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    path.parent().is_none_or(|parent| parent.parent().is_none()) || home.as_deref() == Some(path)
+}
+
+/// Returns true if `path` is on another filesystem than its parent directory.
+fn is_mount_point(path: &Path) -> bool {
+    let Some(parent) = path.parent() else { return false };
+    match (sys::device_id(path), sys::device_id(parent)) {
+        (Ok(device), Ok(parent_device)) => device != parent_device,
+        _ => false,
+    }
+}
@@ -0,0 +1,305 @@
+//! Rotation of three or more items, the generalization of a swap.
+//!
+//! With `--name-swap`, the names rotate while every item stays in its own directory: the first
+//! item gets the name of the second, the second the name of the third, and the last the name of
+//! the first. Otherwise the items rotate through the directories and keep their names.
+//! `--reverse` rotates the other way round.
+//!
+//! Every item is first renamed to a temporary name in its own directory, then each temporary
+//! entry is renamed to its destination. Each rename is atomic; if one fails, or the rotation is
+//! cancelled, the completed ones are undone in reverse order. The directories are pinned once
+//! validated, and no rename replaces an entry that appeared in the meantime. Rotations never
+//! copy: moving an item to another filesystem is refused up front.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use crate::{
+    absolute_lexical, change_owner, failed_after_swap, lock, check_cancelled, check_destination_parents, check_entry_limit, check_free_space_reserve, check_fuse,
+    check_required_fstype, check_required_types, generate_temporary_path, is_inside_by_identity, is_same_entry,
+    metrics, mmv, normalized_name, parent_of, preflight, CHANGES_PENDING, OutcomeStart, plan, print_summary, smoke_test, suggest_cd, sys, windows, Cli, PathPair, SwapError, TEMP_ATTEMPTS,
+};
+
+/// Rotates the items designated by `args`, in that order.
+pub fn rotate(cli: &Cli, args: &[&Path], cancel: &AtomicBool) -> Result<(), SwapError> {
+    if cli.atomic_only {
+        return Err(SwapError::NotAtomic(format!("rotating {} items takes one rename per item", args.len())));
+    }
+    let start = OutcomeStart::now();
+    preflight(args, cli)?;
+    let paths = resolve(args, cli)?;
+    let dests = destinations(&paths, cli)?;
+    check_destinations(&paths, &dests)?;
+    check_same_filesystem(&paths, &dests)?;
+    let items: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+    let targets: Vec<&Path> = dests.iter().map(PathBuf::as_path).collect();
+    check_destination_parents(&targets, cli)?;
+    let moves: Vec<(&Path, &Path)> = items.iter().copied().zip(targets.iter().copied()).collect();
+    windows::check(&moves, cli)?;
+    check_required_types(args, cli)?;
+    check_required_fstype(&items, cli)?;
+    check_fuse(&items, cli)?;
+    let arrivals = moves.iter().map(|&(item, dest)| Ok((item, parent_of(dest)?))).collect::<Result<Vec<_>, SwapError>>()?;
+    check_free_space_reserve(&arrivals, cli)?;
+    check_entry_limit(&items, cli)?;
+
+    if cli.explain {
+        println!("{}", describe(&paths, &dests, cli));
+        return Ok(());
+    }
+    if cli.dry_run && (paths != dests || cli.chown.is_some()) {
+        CHANGES_PENDING.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+    if cli.dry_run && (cli.json || cli.emit_mmv) {
+        let moves: Vec<_> = paths.iter().zip(&dests).map(|(from, to)| plan::Move { from: from.clone(), to: to.clone() }).collect();
+        if cli.emit_mmv {
+            return mmv::print(&moves);
+        }
+        let plan = plan::Plan {
+            version: plan::Version,
+            mode: plan::Mode::Rotation,
+            moves,
+            quarantined: Vec::new(),
+            rollback_safe: true,
+            rollback_hazards: Vec::new(),
+            risks: Vec::new(),
+        };
+        println!("{}", plan.to_json());
+        return Ok(());
+    }
+    if cli.dry_run {
+        for (path, dest) in paths.iter().zip(&dests) {
+            println!("Would move '{}' -> '{}'", path.display(), dest.display());
+        }
+        return Ok(());
+    }
+
+    let parents: Vec<&Path> = paths.iter().filter_map(|path| path.parent()).collect();
+    let _locks = if cli.lock_dir { lock::acquire(&parents, cli)? } else { Vec::new() };
+
+    let cwd = if cli.suggest_cd { std::env::current_dir().ok() } else { None };
+    let dirs = parents.iter()
+        .map(|parent| sys::PinnedDir::open(parent).map_err(|e| SwapError::Io(e, parent.to_path_buf())))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut done: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let result = move_all(&paths, &dests, &dirs, &mut done, cancel, cli);
+    if let Err(cause) = result {
+        return Err(roll_back(&dirs, &done, cause, cli));
+    }
+    metrics::add(&metrics::SWAPS, 1);
+    if let Some(cwd) = cwd {
+        let moves: Vec<(&Path, &Path)> = paths.iter().zip(&dests).map(|(path, dest)| (path.as_path(), dest.as_path())).collect();
+        suggest_cd(&cwd, &moves);
+    }
+
+    if cli.smoke_test {
+        for dest in &dests {
+            smoke_test(dest, cli).map_err(failed_after_swap)?;
+        }
+    }
+
+    if let Some(owner) = &cli.chown {
+        for dest in &dests {
+            change_owner(dest, owner).map_err(failed_after_swap)?;
+            log!(cli, "Changed the ownership of '{}' to {}", dest.display(), owner.spec);
+        }
+    }
+    let moves: Vec<_> = paths.into_iter().zip(dests).map(|(from, to)| plan::Move { from, to }).collect();
+    if cli.summary {
+        print_summary(&moves);
+    }
+    if cli.json {
+        println!("{}", start.finish(plan::Mode::Rotation, moves, Vec::new()).to_json());
+    }
+    Ok(())
+}
+
+/// Resolves every argument like the two paths of a swap, and refuses the same item given twice
+/// or an item inside another one. `--deref-side` is refused for rotations, so every argument
+/// follows the rule of the first side: symlinks are followed unless `--no-dereference` is given.
+fn resolve(args: &[&Path], cli: &Cli) -> Result<Vec<PathBuf>, SwapError> {
+    let mut paths = Vec::with_capacity(args.len());
+    for arg in args {
+        let resolved = if cli.dereferences(1) { fs::canonicalize(arg) } else { absolute_lexical(arg) };
+        let path = resolved.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => SwapError::PathNotFound(arg.to_path_buf()),
+            _ => SwapError::Io(e, arg.to_path_buf()),
+        })?;
+        if cli.show_resolved {
+            println!("'{}' -> '{}'", arg.display(), path.display());
+        }
+        paths.push(path);
+    }
+
+    for (index, path) in paths.iter().enumerate() {
+        for other in &paths[index + 1..] {
+            if path == other {
+                return Err(SwapError::SamePath);
+            }
+            // Hard links to the same file are distinct entries, and rotate like any others.
+            if is_same_entry(path, other) {
+                return Err(SwapError::SameInode { path1: path.clone(), path2: other.clone() });
+            }
+            let nested = |outer: &Path, inner: &Path| {
+                (outer.is_dir() && inner.starts_with(outer)) || is_inside_by_identity(outer, inner)
+            };
+            if nested(path, other) || nested(other, path) {
+                return Err(SwapError::SwapIntoSubdirectory);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Returns where each item ends up: in its own directory under the name of the next item with
+/// `--name-swap`, or under its own name in the directory of the next item otherwise.
+fn destinations(paths: &[PathBuf], cli: &Cli) -> Result<Vec<PathBuf>, SwapError> {
+    let count = paths.len();
+
+    let mut dests = Vec::with_capacity(count);
+    for (index, path) in paths.iter().enumerate() {
+        let next = &paths[if cli.reverse { (index + count - 1) % count } else { (index + 1) % count }];
+        let pair = PathPair::new(path, next)?;
+        let dest = if cli.name_swap {
+            pair.parent1.join(normalized_name(pair.name2, cli))
+        } else {
+            pair.parent2.join(normalized_name(pair.name1, cli))
+        };
+        dests.push(dest);
+    }
+    Ok(dests)
+}
+
+/// Refuses a rotation that would put two items at the same place, which happens when several
+/// items share a directory, or that would replace an entry that is not part of the rotation.
+fn check_destinations(paths: &[PathBuf], dests: &[PathBuf]) -> Result<(), SwapError> {
+    for (index, dest) in dests.iter().enumerate() {
+        if dests[..index].contains(dest) {
+            return Err(SwapError::RotationCollision(dest.clone()));
+        }
+        if !paths.contains(dest) && dest.symlink_metadata().is_ok() {
+            return Err(SwapError::DestinationExists(dest.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Refuses a rotation that would move an item to another filesystem, before anything is moved.
+/// With `--name-swap`, every item stays in its own directory.
+fn check_same_filesystem(paths: &[PathBuf], dests: &[PathBuf]) -> Result<(), SwapError> {
+    let device = |path: &Path| {
+        let parent = parent_of(path)?;
+        sys::device_id(parent).map_err(|e| SwapError::Io(e, parent.to_path_buf()))
+    };
+    for (path, dest) in paths.iter().zip(dests) {
+        if device(path)? != device(dest)? {
+            return Err(SwapError::RotationAcrossFilesystems(path.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Builds the paragraph printed by `swap explain` for a rotation.
+fn describe(paths: &[PathBuf], dests: &[PathBuf], cli: &Cli) -> String {
+    let verb = if cli.name_swap { "rename" } else { "move" };
+    let moves: Vec<String> = paths.iter()
+        .zip(dests)
+        .map(|(path, dest)| format!("'{}' to '{}'", path.display(), dest.display()))
+        .collect();
+    format!(
+        "This will rotate {} items: {} {}. Every item is first renamed to a temporary name in its own directory, \
+         then to its destination; each rename is atomic, but the rotation as a whole is not.",
+        paths.len(), verb, moves.join(", ")
+    )
+}
+
+/// Renames every item to a temporary name, then every temporary entry to its destination,
+/// recording each completed rename in `done`. `dirs` holds the pinned directories of the items,
+/// which are also the directories of the destinations.
+fn move_all(
+    paths: &[PathBuf],
+    dests: &[PathBuf],
+    dirs: &[sys::PinnedDir],
+    done: &mut Vec<(PathBuf, PathBuf)>,
+    cancel: &AtomicBool,
+    cli: &Cli,
+) -> Result<(), SwapError> {
+    let mut temps = Vec::with_capacity(paths.len());
+    for path in paths {
+        temps.push(stage(path, dirs, done, cancel, cli)?);
+    }
+    for (temp, dest) in temps.iter().zip(dests) {
+        step(temp, dest, dirs, done, cancel, cli)?;
+    }
+    Ok(())
+}
+
+/// Returns the pinned directory holding `path`, and the name of `path` in it.
+fn pinned<'a>(dirs: &'a [sys::PinnedDir], path: &'a Path) -> Result<(&'a sys::PinnedDir, &'a OsStr), SwapError> {
+    let parent = parent_of(path)?;
+    let name = path.file_name().ok_or_else(|| SwapError::MissingParent(path.to_path_buf()))?;
+    let dir = dirs.iter().find(|dir| dir.path() == parent).ok_or_else(|| SwapError::MissingParent(path.to_path_buf()))?;
+    Ok((dir, name))
+}
+
+/// Renames `from` to `to` through their pinned directories. Every destination was vacated by the
+/// rotation, so an entry found at `to` was created by another process in the meantime: it is
+/// left alone and the rename fails.
+fn rename(dirs: &[sys::PinnedDir], from: &Path, to: &Path) -> Result<(), SwapError> {
+    let (from_dir, from_name) = pinned(dirs, from)?;
+    let (to_dir, to_name) = pinned(dirs, to)?;
+    sys::rename_noreplace_at(from_dir, from_name, to_dir, to_name).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => SwapError::DestinationExists(to.to_path_buf()),
+        _ => SwapError::Io(e, from.to_path_buf()),
+    })
+}
+
+/// Renames `path` to a temporary name in its own directory, never replacing an entry another
+/// process created under that name in the meantime. Returns the temporary path.
+fn stage(path: &Path, dirs: &[sys::PinnedDir], done: &mut Vec<(PathBuf, PathBuf)>, cancel: &AtomicBool, cli: &Cli) -> Result<PathBuf, SwapError> {
+    let (dir, name) = pinned(dirs, path)?;
+
+    let mut attempts = 0;
+    loop {
+        check_cancelled(cancel)?;
+        let temp = generate_temporary_path(path)?;
+        let temp_name = temp.file_name().ok_or_else(|| SwapError::MissingParent(temp.clone()))?;
+        log!(cli, " {}. Moving '{}' -> '{}' (temporary)", done.len() + 1, path.display(), temp.display());
+        match sys::rename_noreplace_at(dir, name, dir, temp_name) {
+            Ok(()) => {
+                done.push((path.to_path_buf(), temp.clone()));
+                return Ok(temp);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                attempts += 1;
+                if attempts == TEMP_ATTEMPTS {
+                    return Err(SwapError::TempRaceDetected(temp));
+                }
+                metrics::add(&metrics::TEMP_RETRIES, 1);
+                log!(cli, "    '{}' was created by another process, trying another temporary name.", temp.display());
+            }
+            Err(e) => return Err(SwapError::Io(e, path.to_path_buf())),
+        }
+    }
+}
+
+fn step(from: &Path, to: &Path, dirs: &[sys::PinnedDir], done: &mut Vec<(PathBuf, PathBuf)>, cancel: &AtomicBool, cli: &Cli) -> Result<(), SwapError> {
+    check_cancelled(cancel)?;
+    log!(cli, " {}. Moving '{}' -> '{}'", done.len() + 1, from.display(), to.display());
+    rename(dirs, from, to)?;
+    done.push((from.to_path_buf(), to.to_path_buf()));
+    Ok(())
+}
+
+/// Undoes the completed renames, most recent first, through the same pinned directories.
+fn roll_back(dirs: &[sys::PinnedDir], done: &[(PathBuf, PathBuf)], cause: SwapError, cli: &Cli) -> SwapError {
+    for (from, to) in done.iter().rev() {
+        log!(cli, "    Rolling back '{}' -> '{}'", to.display(), from.display());
+        if let Err(rollback) = rename(dirs, to, from) {
+            return SwapError::RollbackFailed { cause: Box::new(cause), rollback: Box::new(rollback) };
+        }
+    }
+    cause
+}
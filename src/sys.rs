@@ -0,0 +1,813 @@
+//! Thin wrappers around the platform syscalls that `std::fs` does not expose.
+//!
+//! Every function returns a plain `io::Result` so callers can map failures into
+//! `SwapError` with the path that caused them.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+#[cfg(unix)]
+use std::ffi::CString;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+/// `statfs` magic number of OpenZFS mounts.
+pub const ZFS_SUPER_MAGIC: i64 = 0x2fc1_2fc1;
+/// `statfs` magic number shared by ext2, ext3 and ext4.
+pub const EXT4_SUPER_MAGIC: i64 = 0xef53;
+/// `statfs` magic number of XFS.
+pub const XFS_SUPER_MAGIC: i64 = 0x5846_5342;
+/// `statfs` magic number of Btrfs.
+pub const BTRFS_SUPER_MAGIC: i64 = 0x9123_683e;
+/// `statfs` magic number of tmpfs.
+pub const TMPFS_MAGIC: i64 = 0x0102_1994;
+/// `statfs` magic number of NFS mounts.
+pub const NFS_SUPER_MAGIC: i64 = 0x6969;
+/// `statfs` magic number of F2FS.
+pub const F2FS_SUPER_MAGIC: i64 = 0xf2f5_2010;
+/// `statfs` magic number of overlayfs.
+pub const OVERLAYFS_SUPER_MAGIC: i64 = 0x794c_7630;
+/// `statfs` magic numbers of SMB mounts: `cifs`, `smb3` and the old `smbfs`.
+pub const CIFS_MAGIC_NUMBER: i64 = 0xff53_4d42;
+pub const SMB2_MAGIC_NUMBER: i64 = 0xfe53_4d42;
+pub const SMB_SUPER_MAGIC: i64 = 0x517b;
+/// `statfs` magic number of FAT (`vfat`, `msdos`).
+pub const MSDOS_SUPER_MAGIC: i64 = 0x4d44;
+/// `statfs` magic number of exFAT.
+pub const EXFAT_SUPER_MAGIC: i64 = 0x2011_bab0;
+/// `statfs` magic numbers of NTFS, as mounted by the `ntfs` and `ntfs3` kernel drivers.
+pub const NTFS_SB_MAGIC: i64 = 0x5346_544e;
+pub const NTFS3_SUPER_MAGIC: i64 = 0x7366_746e;
+/// `statfs` magic number of FUSE mounts (sshfs, rclone, ...), whatever the filesystem behind them.
+pub const FUSE_SUPER_MAGIC: i64 = 0x6573_5546;
+
+/// Converts a path into a NUL-terminated C string for the raw syscalls.
+#[cfg(unix)]
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))
+}
+
+/// Converts a single file name into a NUL-terminated C string for the `*at` syscalls.
+#[cfg(target_os = "linux")]
+fn c_name(name: &OsStr) -> io::Result<CString> {
+    c_path(Path::new(name))
+}
+
+/// Identity of a filesystem entry: its device and inode numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileId {
+    pub dev: u64,
+    pub ino: u64,
+}
+
+impl FileId {
+    /// Returns the identity of `path`, without following a final symlink.
+    pub fn of_path(path: &Path) -> io::Result<Self> {
+        Self::of_metadata(&path.symlink_metadata()?)
+    }
+
+    /// Returns the identity described by already fetched metadata.
+    #[cfg(unix)]
+    pub fn of_metadata(metadata: &std::fs::Metadata) -> io::Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Ok(FileId { dev: metadata.dev(), ino: metadata.ino() })
+    }
+
+    #[cfg(not(unix))]
+    pub fn of_metadata(_metadata: &std::fs::Metadata) -> io::Result<Self> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn of_stat(stat: &libc::stat) -> Self {
+        #[allow(clippy::unnecessary_cast)]
+        FileId { dev: stat.st_dev as u64, ino: stat.st_ino as u64 }
+    }
+}
+
+/// A directory held open with `O_PATH`, so that renames stay relative to the directory that was
+/// validated even if its path is swapped for something else (e.g. a symlink) afterwards.
+///
+/// `O_PATH` is Linux-only: elsewhere, macOS included, only the path is kept, and every operation
+/// goes through it again. The identity checks made before each step still catch a replaced item,
+/// but a parent directory swapped for a symlink in between would be followed.
+pub struct PinnedDir {
+    path: PathBuf,
+    #[cfg(target_os = "linux")]
+    fd: OwnedFd,
+}
+
+impl PinnedDir {
+    #[cfg(target_os = "linux")]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let c_path = c_path(path)?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PinnedDir { path: path.to_path_buf(), fd: unsafe { OwnedFd::from_raw_fd(fd) } })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(PinnedDir { path: path.to_path_buf() })
+    }
+
+    /// The path this directory was opened from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the identity of the entry `name` inside this directory, without following symlinks.
+    #[cfg(target_os = "linux")]
+    pub fn entry_id(&self, name: &OsStr) -> io::Result<FileId> {
+        let c_name = c_name(name)?;
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstatat(self.fd.as_raw_fd(), c_name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(FileId::of_stat(&stat))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn entry_id(&self, name: &OsStr) -> io::Result<FileId> {
+        FileId::of_path(&self.path.join(name))
+    }
+
+    /// Opens the regular file `name` inside this directory for reading, refusing a symlink.
+    #[cfg(target_os = "linux")]
+    pub fn open_file(&self, name: &OsStr) -> io::Result<File> {
+        self.open_at(name, libc::O_RDONLY | libc::O_NOFOLLOW, 0)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn open_file(&self, name: &OsStr) -> io::Result<File> {
+        File::open(self.path.join(name))
+    }
+
+    /// Creates the new file `name` inside this directory, failing if it already exists.
+    #[cfg(target_os = "linux")]
+    pub fn create_file(&self, name: &OsStr, mode: u32) -> io::Result<File> {
+        self.open_at(name, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW, mode)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn create_file(&self, name: &OsStr, _mode: u32) -> io::Result<File> {
+        std::fs::OpenOptions::new().write(true).create_new(true).open(self.path.join(name))
+    }
+
+    /// Creates an anonymous `O_TMPFILE` inode on this directory's filesystem, open for reading
+    /// and writing. It has no name (and is freed on close) until `link_anonymous_file` gives it one.
+    #[cfg(target_os = "linux")]
+    pub fn create_anonymous_file(&self, mode: u32) -> io::Result<File> {
+        self.open_at(OsStr::new("."), libc::O_RDWR | libc::O_TMPFILE, mode)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn create_anonymous_file(&self, _mode: u32) -> io::Result<File> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Links a file created by `create_anonymous_file` as `name` inside this directory.
+    /// Fails with `AlreadyExists` instead of replacing an existing entry.
+    #[cfg(target_os = "linux")]
+    pub fn link_anonymous_file(&self, file: &File, name: &OsStr) -> io::Result<()> {
+        // Going through /proc avoids `AT_EMPTY_PATH`, which needs CAP_DAC_READ_SEARCH.
+        let proc_path = c_path(Path::new(&format!("/proc/self/fd/{}", file.as_raw_fd())))?;
+        let c_name = c_name(name)?;
+        let ret = unsafe {
+            libc::linkat(libc::AT_FDCWD, proc_path.as_ptr(), self.fd.as_raw_fd(), c_name.as_ptr(), libc::AT_SYMLINK_FOLLOW)
+        };
+        if ret == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn link_anonymous_file(&self, _file: &File, _name: &OsStr) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Removes the non-directory entry `name` from this directory.
+    #[cfg(target_os = "linux")]
+    pub fn remove_file(&self, name: &OsStr) -> io::Result<()> {
+        let c_name = c_name(name)?;
+        if unsafe { libc::unlinkat(self.fd.as_raw_fd(), c_name.as_ptr(), 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn remove_file(&self, name: &OsStr) -> io::Result<()> {
+        std::fs::remove_file(self.path.join(name))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn open_at(&self, name: &OsStr, flags: libc::c_int, mode: u32) -> io::Result<File> {
+        let c_name = c_name(name)?;
+        let fd = unsafe { libc::openat(self.fd.as_raw_fd(), c_name.as_ptr(), flags | libc::O_CLOEXEC, mode as libc::c_uint) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(File::from(unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+}
+
+/// An entry held open with `O_PATH | O_NOFOLLOW`, recording the identity it had at validation time.
+/// The handle keeps the inode alive even if another process deletes the entry.
+pub struct PinnedEntry {
+    id: FileId,
+    #[cfg(target_os = "linux")]
+    fd: OwnedFd,
+}
+
+impl PinnedEntry {
+    #[cfg(target_os = "linux")]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let c_path = c_path(path)?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PinnedEntry { id: FileId::of_stat(&stat), fd })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(PinnedEntry { id: FileId::of_path(path)? })
+    }
+
+    /// The identity the entry had when it was pinned.
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    /// Returns the current number of hard links of the pinned inode, which drops to 0 once every
+    /// name of it was deleted. `None` where no handle is held.
+    #[cfg(target_os = "linux")]
+    pub fn link_count(&self) -> io::Result<Option<u64>> {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(self.fd.as_raw_fd(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(stat.st_nlink as u64))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn link_count(&self) -> io::Result<Option<u64>> {
+        Ok(None)
+    }
+}
+
+/// Renames `from_name` in `from_dir` to `to_name` in `to_dir`, relative to the pinned directories.
+#[cfg(target_os = "linux")]
+pub fn rename_at(from_dir: &PinnedDir, from_name: &OsStr, to_dir: &PinnedDir, to_name: &OsStr) -> io::Result<()> {
+    let from = c_name(from_name)?;
+    let to = c_name(to_name)?;
+    if unsafe { libc::renameat(from_dir.fd.as_raw_fd(), from.as_ptr(), to_dir.fd.as_raw_fd(), to.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn rename_at(from_dir: &PinnedDir, from_name: &OsStr, to_dir: &PinnedDir, to_name: &OsStr) -> io::Result<()> {
+    std::fs::rename(from_dir.path.join(from_name), to_dir.path.join(to_name))
+}
+
+/// Like `rename_at`, but fails with `AlreadyExists` instead of replacing an existing `to_name`.
+/// Uses `renameat2(RENAME_NOREPLACE)`; where the filesystem lacks it, the check is done just
+/// before the rename, which leaves a tiny window open.
+#[cfg(target_os = "linux")]
+pub fn rename_noreplace_at(from_dir: &PinnedDir, from_name: &OsStr, to_dir: &PinnedDir, to_name: &OsStr) -> io::Result<()> {
+    let from = c_name(from_name)?;
+    let to = c_name(to_name)?;
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            from_dir.fd.as_raw_fd(),
+            from.as_ptr(),
+            to_dir.fd.as_raw_fd(),
+            to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        _ => match io::Error::last_os_error() {
+            e if is_unsupported(&e) => checked_rename(from_dir, from_name, to_dir, to_name),
+            e => Err(e),
+        },
+    }
+}
+
+/// Like `rename_at`, but fails with `AlreadyExists` instead of replacing an existing `to_name`.
+/// Uses `renamex_np(RENAME_EXCL)`, falling back to a check just before the rename.
+#[cfg(target_os = "macos")]
+pub fn rename_noreplace_at(from_dir: &PinnedDir, from_name: &OsStr, to_dir: &PinnedDir, to_name: &OsStr) -> io::Result<()> {
+    let from = c_path(&from_dir.path().join(from_name))?;
+    let to = c_path(&to_dir.path().join(to_name))?;
+    if unsafe { libc::renamex_np(from.as_ptr(), to.as_ptr(), libc::RENAME_EXCL) } == 0 {
+        return Ok(());
+    }
+    match io::Error::last_os_error() {
+        e if is_unsupported(&e) => checked_rename(from_dir, from_name, to_dir, to_name),
+        e => Err(e),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn rename_noreplace_at(from_dir: &PinnedDir, from_name: &OsStr, to_dir: &PinnedDir, to_name: &OsStr) -> io::Result<()> {
+    checked_rename(from_dir, from_name, to_dir, to_name)
+}
+
+/// Fallback of `rename_noreplace_at`: refuses an existing destination, then renames. The
+/// destination is looked up through the pinned directory, the one the rename writes to.
+fn checked_rename(from_dir: &PinnedDir, from_name: &OsStr, to_dir: &PinnedDir, to_name: &OsStr) -> io::Result<()> {
+    match to_dir.entry_id(to_name) {
+        Ok(_) => Err(io::Error::from(io::ErrorKind::AlreadyExists)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => rename_at(from_dir, from_name, to_dir, to_name),
+        Err(e) => Err(e),
+    }
+}
+
+/// Atomically exchanges the two directory entries using `renameat2(RENAME_EXCHANGE)`.
+/// Both entries must exist and live on the same filesystem.
+#[cfg(target_os = "linux")]
+pub fn exchange_at(dir1: &PinnedDir, name1: &OsStr, dir2: &PinnedDir, name2: &OsStr) -> io::Result<()> {
+    let name1 = c_name(name1)?;
+    let name2 = c_name(name2)?;
+
+    // Called through `syscall` so that it also works with libc versions lacking the wrapper (e.g. musl).
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            dir1.fd.as_raw_fd(),
+            name1.as_ptr(),
+            dir2.fd.as_raw_fd(),
+            name2.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    if ret == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Atomically exchanges the two directory entries using `renamex_np(RENAME_SWAP)`.
+/// APFS supports it; other volumes fail with `ENOTSUP`, and two volumes with `EXDEV`.
+#[cfg(target_os = "macos")]
+pub fn exchange_at(dir1: &PinnedDir, name1: &OsStr, dir2: &PinnedDir, name2: &OsStr) -> io::Result<()> {
+    // `PinnedDir` holds no descriptor on macOS, so the swap goes through the paths.
+    let path1 = c_path(&dir1.path().join(name1))?;
+    let path2 = c_path(&dir2.path().join(name2))?;
+    if unsafe { libc::renamex_np(path1.as_ptr(), path2.as_ptr(), libc::RENAME_SWAP) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn exchange_at(_dir1: &PinnedDir, _name1: &OsStr, _dir2: &PinnedDir, _name2: &OsStr) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Returns the `statfs` magic number (`f_type`) of the filesystem holding `path`.
+#[cfg(target_os = "linux")]
+pub fn fs_type(path: &Path) -> io::Result<i64> {
+    let c_path = c_path(path)?;
+    let mut buf: libc::statfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut buf) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(buf.f_type as i64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn fs_type(_path: &Path) -> io::Result<i64> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Returns the number of bytes available to unprivileged users on the filesystem holding `path`.
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let c_path = c_path(path)?;
+    let mut buf: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut buf) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(buf.f_bavail as u64 * buf.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Looks up a user in the password database. Returns its uid and primary gid, or `None` if
+/// there is no such user.
+#[cfg(unix)]
+pub fn lookup_user(name: &str) -> io::Result<Option<(u32, u32)>> {
+    let c_name = CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+
+    let code = unsafe { libc::getpwnam_r(c_name.as_ptr(), &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if code != 0 {
+        return Err(io::Error::from_raw_os_error(code));
+    }
+    Ok((!result.is_null()).then_some((passwd.pw_uid, passwd.pw_gid)))
+}
+
+#[cfg(not(unix))]
+pub fn lookup_user(_name: &str) -> io::Result<Option<(u32, u32)>> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Looks up a group in the group database. Returns its gid, or `None` if there is no such group.
+#[cfg(unix)]
+pub fn lookup_group(name: &str) -> io::Result<Option<u32>> {
+    let c_name = CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::group = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+
+    let code = unsafe { libc::getgrnam_r(c_name.as_ptr(), &mut group, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if code != 0 {
+        return Err(io::Error::from_raw_os_error(code));
+    }
+    Ok((!result.is_null()).then_some(group.gr_gid))
+}
+
+#[cfg(not(unix))]
+pub fn lookup_group(_name: &str) -> io::Result<Option<u32>> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Returns the effective user id of the process.
+#[cfg(unix)]
+pub fn effective_uid() -> u32 {
+    unsafe { libc::geteuid() }
+}
+
+#[cfg(not(unix))]
+pub fn effective_uid() -> u32 {
+    u32::MAX
+}
+
+/// Returns true if the process may create and remove entries in the directory `dir`, as judged
+/// with its effective ids.
+#[cfg(unix)]
+pub fn is_writable(dir: &Path) -> bool {
+    let Ok(c_path) = c_path(dir) else { return false };
+    unsafe { libc::faccessat(libc::AT_FDCWD, c_path.as_ptr(), libc::W_OK | libc::X_OK, libc::AT_EACCESS) == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn is_writable(dir: &Path) -> bool {
+    dir.metadata().is_ok_and(|metadata| !metadata.permissions().readonly())
+}
+
+/// The process umask, set for `--umask` and restored when dropped. The umask is process-wide:
+/// this is only meant to wrap a whole swap.
+pub struct Umask {
+    #[cfg(unix)]
+    previous: libc::mode_t,
+}
+
+impl Umask {
+    #[cfg(unix)]
+    pub fn set(mask: u32) -> Self {
+        Umask { previous: unsafe { libc::umask(mask as libc::mode_t) } }
+    }
+
+    #[cfg(not(unix))]
+    pub fn set(_mask: u32) -> Self {
+        Umask {}
+    }
+}
+
+impl Drop for Umask {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::umask(self.previous);
+        }
+    }
+}
+
+/// Returns true if the process belongs to the group `gid`, as its effective or a supplementary group.
+#[cfg(unix)]
+pub fn in_group(gid: u32) -> bool {
+    if unsafe { libc::getegid() } == gid {
+        return true;
+    }
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    let mut groups = vec![0 as libc::gid_t; count.max(0) as usize];
+    let count = unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) };
+    groups.truncate(count.max(0) as usize);
+    groups.contains(&gid)
+}
+
+#[cfg(not(unix))]
+pub fn in_group(_gid: u32) -> bool {
+    false
+}
+
+/// Asks the kernel to drop the cached pages of a synced file, so that reading it back hits
+/// the disk rather than the page cache. Only a hint: failures are ignored.
+#[cfg(target_os = "linux")]
+pub fn drop_cached_pages(file: &File) {
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_cached_pages(_file: &File) {}
+
+/// Creates the device node or FIFO `path` with the type, device number, permissions and
+/// timestamps of `metadata`. Creating a device node requires `CAP_MKNOD`.
+#[cfg(unix)]
+pub fn make_node(path: &Path, metadata: &std::fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let c_path = c_path(path)?;
+    if unsafe { libc::mknod(c_path.as_ptr(), metadata.mode() as libc::mode_t, metadata.rdev() as libc::dev_t) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The umask applied to mknod; chmod does not open the node, which could block on a FIFO.
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(metadata.mode() & 0o7777))?;
+
+    let times = [
+        libc::timespec { tv_sec: metadata.atime() as libc::time_t, tv_nsec: metadata.atime_nsec() as _ },
+        libc::timespec { tv_sec: metadata.mtime() as libc::time_t, tv_nsec: metadata.mtime_nsec() as _ },
+    ];
+    if unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), libc::AT_SYMLINK_NOFOLLOW) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn make_node(_path: &Path, _metadata: &std::fs::Metadata) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Returns the id of the device (filesystem) holding `path`, without following a final symlink.
+pub fn device_id(path: &Path) -> io::Result<u64> {
+    FileId::of_path(path).map(|id| id.dev)
+}
+
+/// Sets the access and modification times of `path` itself, without opening it: a final
+/// symlink is not followed, and neither the permissions of the item nor its type matter.
+#[cfg(unix)]
+pub fn set_times(path: &Path, accessed: std::time::SystemTime, modified: std::time::SystemTime) -> io::Result<()> {
+    let c_path = c_path(path)?;
+    let times = [timespec(accessed), timespec(modified)];
+    if unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), libc::AT_SYMLINK_NOFOLLOW) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Converts `time` into a `timespec`, times before the epoch included.
+#[cfg(unix)]
+fn timespec(time: std::time::SystemTime) -> libc::timespec {
+    let (secs, nanos) = match time.duration_since(std::time::UNIX_EPOCH) {
+        Ok(after) => (after.as_secs() as i64, after.subsec_nanos() as i64),
+        Err(e) => {
+            let before = e.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos as i64),
+            }
+        }
+    };
+    libc::timespec { tv_sec: secs as libc::time_t, tv_nsec: nanos as _ }
+}
+
+/// Sets the extended attribute `name` on `path` itself (a final symlink is not followed).
+#[cfg(target_os = "linux")]
+pub fn set_xattr(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+    let c_path = c_path(path)?;
+    let c_attr = c_name(OsStr::new(name))?;
+    let ret = unsafe { libc::lsetxattr(c_path.as_ptr(), c_attr.as_ptr(), value.as_ptr().cast(), value.len(), 0) };
+    if ret == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_xattr(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Reads the extended attribute `name` of `path` itself, or `None` if it is not set.
+#[cfg(target_os = "linux")]
+pub fn get_xattr(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let c_path = c_path(path)?;
+    let c_attr = c_name(OsStr::new(name))?;
+    loop {
+        let size = unsafe { libc::lgetxattr(c_path.as_ptr(), c_attr.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            return if err.raw_os_error() == Some(libc::ENODATA) { Ok(None) } else { Err(err) };
+        }
+
+        let mut buf = vec![0u8; size as usize];
+        let read = unsafe { libc::lgetxattr(c_path.as_ptr(), c_attr.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        if read >= 0 {
+            buf.truncate(read as usize);
+            return Ok(Some(buf));
+        }
+        // The attribute grew between both calls: ask for its size again.
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_xattr(_path: &Path, _name: &str) -> io::Result<Option<Vec<u8>>> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Lists the names of the extended attributes of `path` itself.
+#[cfg(target_os = "linux")]
+pub fn list_xattrs(path: &Path) -> io::Result<Vec<OsString>> {
+    let c_path = c_path(path)?;
+    loop {
+        let size = unsafe { libc::llistxattr(c_path.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buf = vec![0u8; size as usize];
+        let read = unsafe { libc::llistxattr(c_path.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        if read >= 0 {
+            buf.truncate(read as usize);
+            // The names are NUL-terminated and packed one after the other.
+            return Ok(buf.split(|&byte| byte == 0)
+                .filter(|name| !name.is_empty())
+                .map(|name| OsStr::from_bytes(name).to_os_string())
+                .collect());
+        }
+        // The list grew between both calls: ask for its size again.
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn list_xattrs(_path: &Path) -> io::Result<Vec<OsString>> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Removes the extended attribute `name` from `path` itself.
+#[cfg(target_os = "linux")]
+pub fn remove_xattr(path: &Path, name: &str) -> io::Result<()> {
+    let c_path = c_path(path)?;
+    let c_attr = c_name(OsStr::new(name))?;
+    if unsafe { libc::lremovexattr(c_path.as_ptr(), c_attr.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn remove_xattr(_path: &Path, _name: &str) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// An inotify watch on the entries created, written or touched in a directory, used by
+/// `swap watch` to sleep until its trigger file may have appeared.
+#[cfg(target_os = "linux")]
+pub struct DirWatch {
+    fd: OwnedFd,
+}
+
+#[cfg(target_os = "linux")]
+impl DirWatch {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let c_path = c_path(dir)?;
+        let mask = libc::IN_CREATE | libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_ATTRIB;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_path.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(DirWatch { fd })
+    }
+
+    /// Waits up to `timeout` for an event, and discards the pending ones.
+    /// Events are only a hint to look at the directory again: they are not decoded.
+    pub fn wait(&self, timeout: std::time::Duration) -> io::Result<()> {
+        let mut poll = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        if unsafe { libc::poll(&mut poll, 1, timeout) } < 0 {
+            let err = io::Error::last_os_error();
+            // A signal, such as the SIGINT stopping the watch, interrupts the poll.
+            return if err.kind() == io::ErrorKind::Interrupted { Ok(()) } else { Err(err) };
+        }
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+        Ok(())
+    }
+}
+
+/// Without inotify, `wait` only sleeps and the caller polls the directory.
+#[cfg(not(target_os = "linux"))]
+pub struct DirWatch;
+
+#[cfg(not(target_os = "linux"))]
+impl DirWatch {
+    pub fn open(_dir: &Path) -> io::Result<Self> {
+        Ok(DirWatch)
+    }
+
+    pub fn wait(&self, timeout: std::time::Duration) -> io::Result<()> {
+        std::thread::sleep(timeout.min(std::time::Duration::from_millis(500)));
+        Ok(())
+    }
+}
+
+/// Installs a SIGINT handler that raises the returned flag instead of killing the process,
+/// so an interrupted swap can stop between two steps and roll back.
+pub fn interrupt_flag() -> &'static AtomicBool {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+    &INTERRUPTED
+}
+
+/// Returns true if the error is `EXDEV`, i.e. a rename across two filesystems.
+pub fn is_cross_device(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        err.raw_os_error() == Some(libc::EXDEV)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = err;
+        false
+    }
+}
+
+/// Returns true if the error is `EPERM` or `ENOSYS`, which FUSE filesystems return for renames
+/// they do not implement, such as renaming over an existing entry.
+pub fn is_rename_refused(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        err.raw_os_error().is_some_and(|code| code == libc::EPERM || code == libc::ENOSYS)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = err;
+        false
+    }
+}
+
+/// Returns true if the error means the filesystem (or kernel) does not implement the requested rename flavour.
+pub fn is_unsupported(err: &io::Error) -> bool {
+    if err.kind() == io::ErrorKind::Unsupported {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        // `ENOTSUP` and `EOPNOTSUPP` are the same on Linux, but not on macOS.
+        let codes = [libc::EINVAL, libc::ENOSYS, libc::EOPNOTSUPP, libc::ENOTSUP];
+        err.raw_os_error().is_some_and(|code| codes.contains(&code))
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
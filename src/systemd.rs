@@ -0,0 +1,66 @@
+//! `--via-systemd-run`: hands the swap over to a transient systemd unit instead of performing it,
+//! so that it is logged to the journal and runs under the resource limits of the unit.
+//!
+//! The unit runs the same `swap` binary with the same arguments, minus `--via-systemd-run`, in
+//! the current directory, so relative paths keep their meaning. The environment is not passed
+//! on: `--expand` sees the one of the unit.
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::{shell_quote, SwapError, SystemdRun};
+
+/// Prints the `systemd-run` command reproducing this invocation, or runs it with `SystemdRun::Run`.
+pub fn hand_over(mode: SystemdRun) -> Result<(), SwapError> {
+    let command = command()?;
+    if mode == SystemdRun::Print {
+        let words: Vec<String> = command.iter().map(|word| quote(word)).collect();
+        println!("{}", words.join(" "));
+        return Ok(());
+    }
+
+    let status = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .status()
+        .map_err(|e| SwapError::Io(e, PathBuf::from("systemd-run")))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        code => Err(SwapError::SystemdRunFailed(code)),
+    }
+}
+
+/// Builds the `systemd-run` command line: a transient service waited for, with stdio connected
+/// to the caller and removed once finished, even if it failed.
+fn command() -> Result<Vec<OsString>, SwapError> {
+    let program = std::env::current_exe().map_err(|e| SwapError::Io(e, PathBuf::from("swap")))?;
+    let cwd = std::env::current_dir().map_err(|e| SwapError::Io(e, PathBuf::from(".")))?;
+    let unit = format!("swap-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+
+    let mut command: Vec<OsString> = ["systemd-run", "--wait", "--pipe", "--collect", "--quiet"].map(OsString::from).into();
+    command.push(format!("--unit={}", unit).into());
+    command.push("--description=swap".into());
+    let mut working_directory = OsString::from("--working-directory=");
+    working_directory.push(&cwd);
+    command.push(working_directory);
+    command.push("--".into());
+    command.push(program.into_os_string());
+    command.extend(std::env::args_os().skip(1).filter(|arg| !is_own_flag(arg)));
+    Ok(command)
+}
+
+/// Quotes `word` for a POSIX shell, unless it only holds characters that need no quoting.
+fn quote(word: &OsStr) -> String {
+    let plain = word.to_str().filter(|word| {
+        !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+    });
+    match plain {
+        Some(word) => word.to_string(),
+        None => shell_quote(Path::new(word)),
+    }
+}
+
+/// Returns true for `--via-systemd-run` itself, with or without a value.
+fn is_own_flag(arg: &OsStr) -> bool {
+    let Some(arg) = arg.to_str() else { return false };
+    arg == "--via-systemd-run" || arg.starts_with("--via-systemd-run=")
+}
@@ -0,0 +1,106 @@
+//! `--show-tree`: a before/after view of the directories a swap touches.
+//!
+//! Only the direct entries of the affected parent directories are listed, never the whole tree.
+//! Entries are compared by identity, so a name that now holds another item shows up as changed
+//! even though it exists on both sides. Unchanged entries are only counted.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::lock;
+use crate::sys::FileId;
+use crate::SwapError;
+
+/// An entry of a listed directory.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Node {
+    id: Option<FileId>,
+    is_dir: bool,
+}
+
+/// The direct entries of each affected directory, by name.
+pub struct Snapshot {
+    dirs: Vec<(PathBuf, BTreeMap<OsString, Node>)>,
+}
+
+impl Snapshot {
+    /// Lists the entries of `dirs`; a directory given twice is only listed once.
+    pub fn take(dirs: &[&Path]) -> Result<Self, SwapError> {
+        let mut listed: Vec<(PathBuf, BTreeMap<OsString, Node>)> = Vec::new();
+        for dir in dirs {
+            if listed.iter().any(|(listed, _)| listed == dir) {
+                continue;
+            }
+            let io_err = |e| SwapError::Io(e, dir.to_path_buf());
+            let mut entries = BTreeMap::new();
+            for entry in fs::read_dir(dir).map_err(io_err)? {
+                let entry = entry.map_err(io_err)?;
+                // The lock of `--lock-dir` is held while the state after the swap is listed.
+                if entry.file_name() == lock::LOCK_NAME {
+                    continue;
+                }
+                let metadata = entry.path().symlink_metadata().map_err(io_err)?;
+                let node = Node { id: FileId::of_metadata(&metadata).ok(), is_dir: metadata.is_dir() };
+                entries.insert(entry.file_name(), node);
+            }
+            listed.push((dir.to_path_buf(), entries));
+        }
+        Ok(Snapshot { dirs: listed })
+    }
+
+    /// Returns the state the directories will be in once every `(from, to)` move is done.
+    /// A move to a directory that is not listed only removes the entry.
+    pub fn predict(&self, moves: &[(&Path, &Path)]) -> Self {
+        let mut after = Snapshot { dirs: self.dirs.clone() };
+        let mut moved = Vec::new();
+        for (from, to) in moves {
+            moved.push((to, after.entries(from).and_then(|(entries, name)| entries.remove(&name))));
+        }
+        for (to, node) in moved {
+            if let (Some((entries, name)), Some(node)) = (after.entries(to), node) {
+                entries.insert(name, node);
+            }
+        }
+        after
+    }
+
+    fn entries(&mut self, path: &Path) -> Option<(&mut BTreeMap<OsString, Node>, OsString)> {
+        let (parent, name) = (path.parent()?, path.file_name()?);
+        let (_, entries) = self.dirs.iter_mut().find(|(dir, _)| dir == parent)?;
+        Some((entries, name.to_os_string()))
+    }
+}
+
+/// Prints each directory as two columns, its changed entries before and after, followed by
+/// the number of entries left alone. Changed rows are marked with `*`.
+pub fn print(before: &Snapshot, after: &Snapshot) {
+    for ((dir, old), (_, new)) in before.dirs.iter().zip(&after.dirs) {
+        let label = |entries: &BTreeMap<OsString, Node>, name: &OsString| match entries.get(name) {
+            Some(node) if node.is_dir => format!("{}/", name.to_string_lossy()),
+            Some(_) => name.to_string_lossy().into_owned(),
+            None => "-".to_string(),
+        };
+
+        let mut names: Vec<&OsString> = old.keys().chain(new.keys()).collect();
+        names.sort();
+        names.dedup();
+        let (changed, unchanged): (Vec<&OsString>, Vec<&OsString>) =
+            names.into_iter().partition(|name| old.get(*name) != new.get(*name));
+
+        let rows: Vec<(String, String)> = changed.iter().map(|name| (label(old, name), label(new, name))).collect();
+        let width = rows.iter().map(|(left, _)| left.chars().count()).max().unwrap_or(0).max("before".len());
+
+        println!("{}", dir.join("").display());
+        println!("    {:<width$}   after", "before", width = width);
+        for (left, right) in rows {
+            println!("  * {:<width$}   {}", left, right, width = width);
+        }
+        match unchanged.len() {
+            0 => {}
+            1 => println!("    (1 unchanged entry)"),
+            count => println!("    ({} unchanged entries)", count),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! `swap watch`: swaps two items every time a trigger file appears, for "touch to deploy"
+//! setups such as blue/green directory swaps.
+//!
+//! The trigger is removed before each swap, so a trigger created while a swap is in progress is
+//! a new one and causes the next swap. Whether to swap is always decided by looking for the
+//! trigger: inotify events (on Linux; elsewhere the directory is polled) only wake the watch up.
+//! A failed swap is reported and the watch goes on; Ctrl-C stops it, rolling back a swap in progress.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime};
+
+use clap::Parser;
+
+use crate::{consume_deploy_lock, iso_timestamp, run, sys, Cli, SwapError};
+
+/// How long to wait for an event before checking for an interruption.
+const WAKE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Implements `swap watch`: swaps as described by `args` each time `trigger` is created or touched.
+pub fn watch(trigger: &Path, args: &[OsString]) -> Result<(), SwapError> {
+    let program = OsString::from("swap");
+    let mut cli = Cli::parse_from(std::iter::once(&program).chain(args));
+    let Some((path1, path2)) = cli.paths()? else {
+        clap::Error::raw(clap::error::ErrorKind::MissingRequiredArgument, "`swap watch` needs the two paths to swap\n").exit();
+    };
+    // Metrics describe a single run; a watch keeps running.
+    cli.metrics_file = None;
+
+    // The trigger does not have to exist yet.
+    let trigger = std::path::absolute(trigger).map_err(|e| SwapError::Io(e, trigger.to_path_buf()))?;
+    let dir = trigger.parent().ok_or_else(|| SwapError::MissingParent(trigger.clone()))?;
+    let events = sys::DirWatch::open(dir).map_err(|e| SwapError::Io(e, dir.to_path_buf()))?;
+    let cancel = sys::interrupt_flag();
+    println!("Watching '{}' to swap '{}' and '{}'.", trigger.display(), path1.display(), path2.display());
+
+    let mut swaps = 0;
+    while !cancel.load(Ordering::SeqCst) {
+        if !take_trigger(&trigger)? {
+            events.wait(WAKE_INTERVAL).map_err(|e| SwapError::Io(e, dir.to_path_buf()))?;
+            continue;
+        }
+        match run(&cli, &path1, &path2, cancel) {
+            Ok(()) => {
+                swaps += 1;
+                consume_deploy_lock(&cli);
+                println!("[{}] Swapped '{}' and '{}'.", iso_timestamp(SystemTime::now()), path1.display(), path2.display());
+            }
+            Err(SwapError::Cancelled) => return Err(SwapError::Cancelled),
+            Err(e) => eprintln!("[{}] {}", iso_timestamp(SystemTime::now()), e),
+        }
+    }
+    println!("Stopped watching after {} swaps.", swaps);
+    Ok(())
+}
+
+/// Removes the trigger if it exists. Returns whether it did.
+fn take_trigger(trigger: &Path) -> Result<bool, SwapError> {
+    match std::fs::remove_file(trigger) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(SwapError::Io(e, trigger.to_path_buf())),
+    }
+}
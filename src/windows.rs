@@ -0,0 +1,129 @@
+//! `--windows-names`: refuses final names that Windows, and the filesystems following its rules,
+//! cannot hold, before anything is moved.
+//!
+//! On SMB shares, FAT, exFAT and NTFS, a name ending with a dot or a space, a name holding one
+//! of `<>:"/\|?*` or a control character, and the reserved device names (`CON`, `PRN`, `AUX`,
+//! `NUL`, `COM1` to `COM9` and `LPT1` to `LPT9`, in any case and with any extension, such as
+//! `nul.txt`) either fail with a cryptic error or are silently mangled by the server. By default
+//! names are only checked on these filesystems; `always` checks them everywhere, for items
+//! meant to end up on Windows later, and `never` turns the check off. Only names new to a
+//! filesystem are checked: an item keeping its name on its own filesystem already fits there.
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::{sys, Cli, SwapError, WindowsNames};
+
+/// `statfs` magic numbers of the filesystems following the Windows naming rules.
+const WINDOWS_FILESYSTEMS: &[i64] = &[
+    sys::CIFS_MAGIC_NUMBER,
+    sys::SMB2_MAGIC_NUMBER,
+    sys::SMB_SUPER_MAGIC,
+    sys::MSDOS_SUPER_MAGIC,
+    sys::EXFAT_SUPER_MAGIC,
+    sys::NTFS_SB_MAGIC,
+    sys::NTFS3_SUPER_MAGIC,
+];
+
+/// Device names Windows reserves in every directory.
+const RESERVED: &[&str] = &["CON", "PRN", "AUX", "NUL"];
+
+/// Refuses the first destination, among `(item, destination)` pairs, whose name is invalid on
+/// the filesystem of its directory.
+pub fn check(moves: &[(&Path, &Path)], cli: &Cli) -> Result<(), SwapError> {
+    if cli.windows_names == WindowsNames::Never {
+        return Ok(());
+    }
+    for (item, dest) in moves {
+        let (Some(name), Some(parent)) = (dest.file_name(), dest.parent()) else { continue };
+        let moves_in = item.file_name() != Some(name) || sys::device_id(item).ok() != sys::device_id(parent).ok();
+        if !moves_in || problem(name).is_none() {
+            continue;
+        }
+        if cli.windows_names == WindowsNames::Always || follows_windows_rules(parent) {
+            return Err(SwapError::InvalidNameForFilesystem(dest.to_path_buf()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if the filesystem holding `dir` follows the Windows naming rules.
+fn follows_windows_rules(dir: &Path) -> bool {
+    sys::fs_type(dir).is_ok_and(|magic| WINDOWS_FILESYSTEMS.contains(&magic))
+}
+
+/// Describes what makes `name` invalid on Windows, if anything.
+pub fn problem(name: &OsStr) -> Option<&'static str> {
+    let Some(name) = name.to_str() else { return Some("it is not valid Unicode") };
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some("it ends with a dot or a space");
+    }
+    if name.chars().any(|c| c.is_control() || "<>:\"\\|?*".contains(c)) {
+        return Some("it holds a character among <>:\"\\|?* or a control character");
+    }
+    if is_reserved(name) {
+        return Some("it is a reserved device name");
+    }
+    None
+}
+
+/// Returns true if `name` is a reserved device name, whatever its case and extension.
+pub fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ').to_ascii_uppercase();
+    if RESERVED.contains(&stem.as_str()) {
+        return true;
+    }
+    let numbered = stem.strip_prefix("COM").or_else(|| stem.strip_prefix("LPT"));
+    numbered.is_some_and(|digit| digit.len() == 1 && matches!(digit.as_bytes()[0], b'1'..=b'9'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem_of(name: &str) -> Option<&'static str> {
+        problem(OsStr::new(name))
+    }
+
+    #[test]
+    fn reserved_names_are_refused_with_any_extension_and_case() {
+        assert!(is_reserved("nul.txt"));
+        assert!(is_reserved("Con"));
+        assert!(is_reserved("aux.tar.gz"));
+        assert_eq!(problem_of("nul.txt"), Some("it is a reserved device name"));
+    }
+
+    #[test]
+    fn only_com1_to_com9_and_lpt1_to_lpt9_are_reserved() {
+        assert!(is_reserved("LPT9"));
+        assert!(is_reserved("com1.log"));
+        assert!(!is_reserved("COM0"));
+        assert!(!is_reserved("LPT10"));
+        assert!(!is_reserved("COMA"));
+        assert_eq!(problem_of("COM0"), None);
+    }
+
+    #[test]
+    fn trailing_spaces_do_not_hide_a_reserved_name() {
+        assert!(is_reserved("con "));
+        assert!(is_reserved("con .txt"));
+        assert_eq!(problem_of("con "), Some("it ends with a dot or a space"));
+        assert_eq!(problem_of("con .txt"), Some("it is a reserved device name"));
+    }
+
+    #[test]
+    fn trailing_dots_and_forbidden_characters_are_refused() {
+        assert_eq!(problem_of("report."), Some("it ends with a dot or a space"));
+        assert_eq!(problem_of("a:b"), Some("it holds a character among <>:\"\\|?* or a control character"));
+        assert_eq!(problem_of("tab\there"), Some("it holds a character among <>:\"\\|?* or a control character"));
+        assert_eq!(problem_of("report.txt"), None);
+        assert_eq!(problem_of(".hidden"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn names_that_are_not_unicode_are_refused() {
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(problem(OsStr::from_bytes(b"caf\xe9")), Some("it is not valid Unicode"));
+    }
+}
@@ -0,0 +1,175 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+use common::{assert_failure, assert_success, stderr, Scratch};
+
+/// The uid and gid of `nobody`, to run without root's privileges.
+const NOBODY: u32 = 65534;
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+fn mode(path: &Path) -> u32 {
+    fs::symlink_metadata(path).unwrap().permissions().mode() & 0o7777
+}
+
+fn mtime(path: &Path) -> SystemTime {
+    fs::symlink_metadata(path).unwrap().modified().unwrap()
+}
+
+fn set_mode(path: &Path, mode: u32) {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+fn set_mtime(path: &Path, secs: u64) {
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+    fs::File::options().write(true).open(path).unwrap().set_times(fs::FileTimes::new().set_modified(time)).unwrap();
+}
+
+fn make_fifo(path: &Path) {
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) }, 0, "mkfifo: {}", std::io::Error::last_os_error());
+}
+
+/// Returns `swap` with `args`, run as the owner of the scratch directory and of `owned`, without
+/// root's privileges: as `nobody` when the tests run as root, so that permissions apply. The
+/// binary is then run from a copy in the scratch directory, as `nobody` may not reach the build one.
+fn unprivileged(scratch: &Scratch, owned: &[&Path], args: &[&str]) -> Command {
+    if !is_root() {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_swap"));
+        command.args(args).current_dir(scratch.root());
+        return command;
+    }
+    let binary = scratch.path(".bin/swap");
+    fs::create_dir_all(binary.parent().unwrap()).unwrap();
+    fs::copy(env!("CARGO_BIN_EXE_swap"), &binary).unwrap();
+    set_mode(binary.parent().unwrap(), 0o755);
+    for path in std::iter::once(scratch.root()).chain(owned.iter().copied()) {
+        std::os::unix::fs::lchown(path, Some(NOBODY), Some(NOBODY)).unwrap();
+    }
+    let mut command = Command::new(binary);
+    command.args(args).current_dir(scratch.root()).uid(NOBODY).gid(NOBODY);
+    command
+}
+
+#[test]
+fn mode_and_mtime_are_exchanged() {
+    let scratch = Scratch::new();
+    let (a, b) = (scratch.file("a", "first"), scratch.file("b", "second"));
+    set_mode(&a, 0o640);
+    set_mode(&b, 0o755);
+    set_mtime(&a, 1_000_000);
+    set_mtime(&b, 2_000_000);
+
+    assert_success(&scratch.swap(["--attributes-only", "a", "b"]));
+
+    assert_eq!((mode(&a), mode(&b)), (0o755, 0o640));
+    assert_eq!(mtime(&a), SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000));
+    assert_eq!(mtime(&b), SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000));
+    assert_eq!((scratch.read("a"), scratch.read("b")), ("first".to_string(), "second".to_string()));
+}
+
+#[test]
+fn preserve_restricts_the_exchange() {
+    let scratch = Scratch::new();
+    let (a, b) = (scratch.file("a", "first"), scratch.file("b", "second"));
+    set_mode(&a, 0o600);
+    set_mode(&b, 0o644);
+    set_mtime(&a, 1_000_000);
+    set_mtime(&b, 2_000_000);
+
+    assert_success(&scratch.swap(["--attributes-only", "--preserve", "mode", "a", "b"]));
+
+    assert_eq!((mode(&a), mode(&b)), (0o644, 0o600));
+    assert_eq!(mtime(&a), SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000));
+    assert_eq!(mtime(&b), SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000));
+}
+
+/// The timestamps are set once the new mode is applied: opening the item then would be refused.
+#[test]
+fn a_write_only_item_is_handled_without_privileges() {
+    let scratch = Scratch::new();
+    let (a, b) = (scratch.file("a", "first"), scratch.file("b", "second"));
+    set_mode(&a, 0o644);
+    set_mode(&b, 0o200);
+    set_mtime(&a, 1_000_000);
+    set_mtime(&b, 2_000_000);
+
+    let output = unprivileged(&scratch, &[&a, &b], &["--attributes-only", "a", "b"]).output().unwrap();
+
+    assert_success(&output);
+    assert_eq!((mode(&a), mode(&b)), (0o200, 0o644));
+    assert_eq!(mtime(&a), SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000));
+    assert_eq!(mtime(&b), SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000));
+}
+
+/// Opening a FIFO blocks until a writer shows up: the item must never be opened.
+#[test]
+fn a_fifo_does_not_block_the_exchange() {
+    let scratch = Scratch::new();
+    let file = scratch.file("file", "contents");
+    let fifo = scratch.path("fifo");
+    make_fifo(&fifo);
+    set_mode(&file, 0o600);
+    set_mtime(&file, 1_000_000);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_swap"))
+        .args(["--attributes-only", "file", "fifo"])
+        .current_dir(scratch.root())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > Duration::from_secs(5) {
+            child.kill().unwrap();
+            panic!("swap --attributes-only blocked on the FIFO");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    assert_success(&child.wait_with_output().unwrap());
+    assert_eq!((mode(&file), mode(&fifo)), (0o644, 0o600));
+    assert_eq!(mtime(&fifo), SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000));
+}
+
+/// Applying the mode to the second item, owned by root, fails: the first one gets its own back.
+#[test]
+fn the_first_item_is_restored_when_the_second_fails() {
+    if !is_root() {
+        return;
+    }
+    let scratch = Scratch::new();
+    let (mine, theirs) = (scratch.file("mine", "mine"), scratch.file("theirs", "theirs"));
+    set_mode(&mine, 0o600);
+    set_mode(&theirs, 0o644);
+
+    let output = unprivileged(&scratch, &[&mine], &["--attributes-only", "--preserve", "mode", "mine", "theirs"]).output().unwrap();
+
+    assert_failure(&output, "theirs");
+    assert!(stderr(&output).contains("Operation not permitted"), "{}", stderr(&output));
+    assert_eq!((mode(&mine), mode(&theirs)), (0o600, 0o644));
+    assert_eq!(fs::metadata(&theirs).unwrap().uid(), 0);
+}
+
+#[test]
+fn symlinks_are_refused() {
+    let scratch = Scratch::new();
+    scratch.file("target", "target");
+    scratch.file("file", "file");
+    std::os::unix::fs::symlink("target", scratch.path("link")).unwrap();
+
+    assert_failure(&scratch.swap(["--attributes-only", "--no-dereference", "file", "link"]), "does not exchange the attributes of symlinks");
+}
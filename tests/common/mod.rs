@@ -0,0 +1,109 @@
+//! Helpers shared by the integration tests: scratch directories and running the binary.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A directory removed with everything in it when dropped.
+pub struct Scratch {
+    root: PathBuf,
+}
+
+impl Scratch {
+    /// Creates a scratch directory under the system temporary directory.
+    pub fn new() -> Self {
+        Scratch::under(&std::env::temp_dir())
+    }
+
+    /// Creates a scratch directory under `base`, to test on the filesystem holding it.
+    pub fn under(base: &Path) -> Self {
+        let root = base.join(format!("swap-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        Scratch { root: fs::canonicalize(root).unwrap() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    /// Writes `contents` to the file `relative`, creating its parent directories.
+    pub fn file(&self, relative: &str, contents: &str) -> PathBuf {
+        let path = self.path(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    pub fn dir(&self, relative: &str) -> PathBuf {
+        let path = self.path(relative);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    pub fn read(&self, relative: &str) -> String {
+        fs::read_to_string(self.path(relative)).unwrap()
+    }
+
+    /// The names in the directory `relative`, sorted.
+    pub fn list(&self, relative: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(self.path(relative))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Runs `swap` with `args` from the scratch directory.
+    pub fn swap<I, S>(&self, args: I) -> Output
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        Command::new(env!("CARGO_BIN_EXE_swap")).args(args).current_dir(&self.root).output().unwrap()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// Asserts that the run exited with status 0, showing its output otherwise.
+pub fn assert_success(output: &Output) {
+    assert!(output.status.success(), "swap failed: {}{}", stdout(output), stderr(output));
+}
+
+/// Asserts that the run failed with an error message containing `message`.
+pub fn assert_failure(output: &Output, message: &str) {
+    assert!(!output.status.success(), "swap succeeded: {}", stdout(output));
+    assert!(stderr(output).contains(message), "unexpected error: {}", stderr(output));
+}
+
+/// Scratch directories on two filesystems, so that swaps between them copy: under the system
+/// temporary directory and under `/dev/shm`. `None` when both are on the same filesystem, or
+/// `/dev/shm` is missing, so that the tests needing a copy are skipped.
+#[cfg(target_os = "linux")]
+pub fn across_filesystems() -> Option<(Scratch, Scratch)> {
+    use std::os::unix::fs::MetadataExt;
+    let shm = Path::new("/dev/shm");
+    let device = |path: &Path| fs::metadata(path).map(|metadata| metadata.dev()).ok();
+    if device(shm).is_none() || device(shm) == device(&std::env::temp_dir()) {
+        return None;
+    }
+    Some((Scratch::new(), Scratch::under(shm)))
+}
@@ -0,0 +1,65 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+
+use common::{across_filesystems, assert_success, Scratch};
+
+/// A tree holding two hard links to one file, and a file linked from outside the tree.
+fn hard_linked_tree(scratch: &Scratch) {
+    scratch.file("a/tree/one", "linked");
+    scratch.file("a/tree/three", "three");
+    scratch.dir("a/tree/sub");
+    fs::hard_link(scratch.path("a/tree/one"), scratch.path("a/tree/sub/two")).unwrap();
+    fs::hard_link(scratch.path("a/tree/three"), scratch.path("a/outside")).unwrap();
+}
+
+fn metadata(scratch: &Scratch, relative: &str) -> fs::Metadata {
+    fs::symlink_metadata(scratch.path(relative)).unwrap()
+}
+
+#[test]
+fn hard_links_within_a_copied_tree_are_preserved() {
+    let Some((scratch, other)) = across_filesystems() else { return };
+    hard_linked_tree(&scratch);
+    let item = other.file("b/item", "item");
+
+    assert_success(&scratch.swap([scratch.path("a/tree").as_os_str(), item.as_os_str()]));
+
+    let (one, two, three) = (metadata(&other, "b/tree/one"), metadata(&other, "b/tree/sub/two"), metadata(&other, "b/tree/three"));
+    assert_eq!(one.ino(), two.ino());
+    assert_eq!(one.nlink(), 2);
+    // The link from outside the tree does not come along.
+    assert_eq!(three.nlink(), 1);
+    assert_eq!(other.read("b/tree/sub/two"), "linked");
+    assert_eq!(scratch.read("a/item"), "item");
+}
+
+#[test]
+fn hard_links_are_copied_as_separate_files_when_not_preserved() {
+    let Some((scratch, other)) = across_filesystems() else { return };
+    hard_linked_tree(&scratch);
+    let item = other.file("b/item", "item");
+
+    assert_success(&scratch.swap(["--preserve-hardlinks=false".as_ref(), scratch.path("a/tree").as_os_str(), item.as_os_str()]));
+
+    let (one, two) = (metadata(&other, "b/tree/one"), metadata(&other, "b/tree/sub/two"));
+    assert_ne!(one.ino(), two.ino());
+    assert_eq!(one.nlink(), 1);
+    assert_eq!(other.read("b/tree/sub/two"), "linked");
+}
+
+#[test]
+fn a_tree_copied_back_keeps_its_hard_links() {
+    let Some((scratch, other)) = across_filesystems() else { return };
+    hard_linked_tree(&scratch);
+    let item = other.file("b/item", "item");
+
+    assert_success(&scratch.swap([scratch.path("a/tree").as_os_str(), item.as_os_str()]));
+    assert_success(&scratch.swap([other.path("b/tree").as_os_str(), scratch.path("a/item").as_os_str()]));
+
+    assert_eq!(metadata(&scratch, "a/tree/one").ino(), metadata(&scratch, "a/tree/sub/two").ino());
+    assert_eq!(other.read("b/item"), "item");
+}
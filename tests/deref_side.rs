@@ -0,0 +1,93 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{assert_failure, assert_success, Scratch};
+
+/// `current -> releases/v1`, and a regular file `staging/build`: the arrangement of the README.
+fn arrangement() -> Scratch {
+    let scratch = Scratch::new();
+    scratch.file("releases/v1", "target");
+    scratch.file("staging/build", "build");
+    std::os::unix::fs::symlink("releases/v1", scratch.path("current")).unwrap();
+    scratch
+}
+
+/// The target is swapped with the file; the symlink stays, dangling.
+fn assert_target_swapped(scratch: &Scratch) {
+    assert_eq!(scratch.read("releases/build"), "build");
+    assert_eq!(scratch.read("staging/v1"), "target");
+    assert_eq!(fs::read_link(scratch.path("current")).unwrap(), Path::new("releases/v1"));
+    assert!(!scratch.path("current").exists());
+}
+
+/// The symlink itself is swapped with the file, and keeps its target as written.
+fn assert_symlink_swapped(scratch: &Scratch) {
+    assert_eq!(scratch.read("build"), "build");
+    assert_eq!(fs::read_link(scratch.path("staging/current")).unwrap(), Path::new("releases/v1"));
+    assert_eq!(scratch.read("releases/v1"), "target");
+}
+
+#[test]
+fn both_sides_are_dereferenced_by_default() {
+    let scratch = arrangement();
+    assert_success(&scratch.swap(["current", "staging/build"]));
+    assert_target_swapped(&scratch);
+}
+
+#[test]
+fn deref_side_both_resolves_the_symlink() {
+    let scratch = arrangement();
+    assert_success(&scratch.swap(["--deref-side", "both", "current", "staging/build"]));
+    assert_target_swapped(&scratch);
+}
+
+#[test]
+fn deref_side_1_resolves_the_symlink_given_first() {
+    let scratch = arrangement();
+    assert_success(&scratch.swap(["--deref-side", "1", "current", "staging/build"]));
+    assert_target_swapped(&scratch);
+}
+
+#[test]
+fn deref_side_2_swaps_the_symlink_given_first_itself() {
+    let scratch = arrangement();
+    assert_success(&scratch.swap(["--deref-side", "2", "current", "staging/build"]));
+    assert_symlink_swapped(&scratch);
+}
+
+#[test]
+fn deref_side_2_resolves_the_symlink_given_second() {
+    let scratch = arrangement();
+    assert_success(&scratch.swap(["--deref-side", "2", "staging/build", "current"]));
+    assert_target_swapped(&scratch);
+}
+
+#[test]
+fn deref_side_none_swaps_the_symlink_itself() {
+    let scratch = arrangement();
+    assert_success(&scratch.swap(["--deref-side", "none", "current", "staging/build"]));
+    assert_symlink_swapped(&scratch);
+}
+
+#[test]
+fn deref_side_conflicts_with_no_dereference() {
+    let scratch = arrangement();
+    assert_failure(&scratch.swap(["--deref-side", "1", "--no-dereference", "current", "staging/build"]), "cannot be used with");
+    assert_eq!(scratch.read("staging/build"), "build");
+}
+
+#[test]
+fn a_destination_through_an_unresolved_symlinked_directory_is_refused() {
+    let scratch = Scratch::new();
+    scratch.file("real/item", "item");
+    scratch.file("other/file", "file");
+    std::os::unix::fs::symlink("real", scratch.path("linked")).unwrap();
+
+    // `linked/item` is not resolved, so the file would land in `real/` through the symlink.
+    assert_failure(&scratch.swap(["--deref-side", "2", "linked/item", "other/file"]), "would be reached through a symlinked directory");
+    assert_eq!(scratch.read("real/item"), "item");
+}
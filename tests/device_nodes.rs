@@ -0,0 +1,75 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use common::{across_filesystems, assert_success, Scratch};
+
+fn make_node(path: &Path, kind: libc::mode_t, major: u32, minor: u32) {
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let ret = unsafe { libc::mknod(c_path.as_ptr(), kind | 0o640, libc::makedev(major, minor)) };
+    assert_eq!(ret, 0, "mknod '{}': {}", path.display(), std::io::Error::last_os_error());
+}
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[test]
+fn device_nodes_are_renamed() {
+    if !is_root() {
+        return;
+    }
+    let scratch = Scratch::new();
+    scratch.dir("dev");
+    make_node(&scratch.path("dev/sda"), libc::S_IFBLK, 8, 0);
+    make_node(&scratch.path("dev/sdb"), libc::S_IFBLK, 8, 16);
+
+    assert_success(&scratch.swap(["--name-swap", "dev/sda", "dev/sdb"]));
+
+    assert_eq!(fs::symlink_metadata(scratch.path("dev/sda")).unwrap().rdev(), libc::makedev(8, 16));
+    assert_eq!(fs::symlink_metadata(scratch.path("dev/sdb")).unwrap().rdev(), libc::makedev(8, 0));
+}
+
+/// The copy must recreate the nodes, never read the devices behind them.
+#[test]
+fn device_nodes_are_recreated_across_filesystems() {
+    if !is_root() {
+        return;
+    }
+    let Some((scratch, other)) = across_filesystems() else { return };
+    scratch.dir("a/nodes");
+    make_node(&scratch.path("a/nodes/block"), libc::S_IFBLK, 7, 0);
+    make_node(&scratch.path("a/nodes/char"), libc::S_IFCHR, 1, 5);
+    let item = other.file("b/item", "item");
+
+    assert_success(&scratch.swap([scratch.path("a/nodes").as_os_str(), item.as_os_str()]));
+
+    let block = fs::symlink_metadata(other.path("b/nodes/block")).unwrap();
+    assert!(block.file_type().is_block_device());
+    assert_eq!(block.rdev(), libc::makedev(7, 0));
+    let char = fs::symlink_metadata(other.path("b/nodes/char")).unwrap();
+    assert!(char.file_type().is_char_device());
+    assert_eq!(char.rdev(), libc::makedev(1, 5));
+    assert_eq!(char.permissions().mode() & 0o7777, 0o640);
+    assert_eq!(scratch.read("a/item"), "item");
+}
+
+/// FIFOs need no privilege, and are recreated the same way: opening one would block.
+#[test]
+fn fifos_are_recreated_across_filesystems() {
+    let Some((scratch, other)) = across_filesystems() else { return };
+    scratch.dir("a");
+    make_node(&scratch.path("a/fifo"), libc::S_IFIFO, 0, 0);
+    let item = other.file("b/item", "item");
+
+    assert_success(&scratch.swap([scratch.path("a/fifo").as_os_str(), item.as_os_str()]));
+
+    assert!(fs::symlink_metadata(other.path("b/fifo")).unwrap().file_type().is_fifo());
+    assert_eq!(scratch.read("a/item"), "item");
+}
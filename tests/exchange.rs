@@ -0,0 +1,134 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::Command;
+
+use common::{assert_success, Scratch};
+
+/// Watches directories with inotify for entries created or renamed in them.
+struct Watch {
+    fd: libc::c_int,
+}
+
+impl Watch {
+    fn new(dirs: &[&Path]) -> Self {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        assert!(fd >= 0, "inotify_init1: {}", std::io::Error::last_os_error());
+        for dir in dirs {
+            let c_path = CString::new(dir.as_os_str().as_bytes()).unwrap();
+            let mask = libc::IN_CREATE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+            assert!(unsafe { libc::inotify_add_watch(fd, c_path.as_ptr(), mask) } >= 0);
+        }
+        Watch { fd }
+    }
+
+    /// The names of the entries the events so far were about.
+    fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            let read = unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) };
+            if read <= 0 {
+                return names;
+            }
+            let mut offset = 0;
+            while offset < read as usize {
+                let event = unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast::<libc::inotify_event>()) };
+                let start = offset + std::mem::size_of::<libc::inotify_event>();
+                let name = &buf[start..start + event.len as usize];
+                let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+                names.push(String::from_utf8_lossy(name).into_owned());
+                offset = start + event.len as usize;
+            }
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+#[test]
+fn the_exchange_creates_no_temporary_entry() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/x", "other");
+    let watch = Watch::new(&[&scratch.path("a"), &scratch.path("b")]);
+
+    assert_success(&scratch.swap(["a/x", "b/x"]));
+
+    let names = watch.names();
+    assert!(!names.is_empty(), "no event seen");
+    assert!(names.iter().all(|name| name == "x"), "{:?}", names);
+    assert_eq!(scratch.read("a/x"), "other");
+}
+
+/// The control: the same watch sees the temporary name of the renames.
+#[test]
+fn the_renames_go_through_a_temporary_entry() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+    let watch = Watch::new(&[&scratch.path("a"), &scratch.path("b")]);
+
+    assert_success(&scratch.swap(["a/x", "b/y"]));
+
+    let names = watch.names();
+    assert!(names.iter().any(|name| name.starts_with("x.swap.")), "{:?}", names);
+}
+
+/// Runs `swap` with `args` from the scratch directory under `strace -f`, tracing the system calls
+/// taking a path, and returns the trace. `None` where strace is missing or cannot trace.
+fn traced(scratch: &Scratch, args: &[&str]) -> Option<String> {
+    let usable = Command::new("strace").args(["-o", "/dev/null", "true"]).output().is_ok_and(|output| output.status.success());
+    if !usable {
+        return None;
+    }
+    let log = scratch.path("strace.log");
+    let output = Command::new("strace")
+        .args(["-f", "-qq", "-e", "trace=%file", "-o"])
+        .arg(&log)
+        .arg(env!("CARGO_BIN_EXE_swap"))
+        .args(args)
+        .current_dir(scratch.root())
+        .output()
+        .unwrap();
+    assert_success(&output);
+    Some(std::fs::read_to_string(log).unwrap())
+}
+
+/// The exchange is a single `renameat2` call, and no system call names a temporary path: no
+/// temporary name is ever generated, looked up or created.
+#[test]
+fn the_exchange_is_a_single_syscall_without_temporary_path() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/x", "other");
+
+    let Some(trace) = traced(&scratch, &["a/x", "b/x"]) else { return };
+
+    let renames: Vec<&str> = trace.lines().filter(|line| line.contains("rename")).collect();
+    assert_eq!(renames.len(), 1, "{:?}", renames);
+    assert!(renames[0].contains("RENAME_EXCHANGE"), "{}", renames[0]);
+    assert!(!trace.contains(".swap."), "{}", trace);
+    assert_eq!(scratch.read("a/x"), "other");
+}
+
+/// The control: the renames look up and create their temporary path.
+#[test]
+fn the_renames_are_traced_with_their_temporary_path() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    let Some(trace) = traced(&scratch, &["a/x", "b/y"]) else { return };
+
+    assert_eq!(trace.lines().filter(|line| line.contains("rename")).count(), 3, "{}", trace);
+    assert!(trace.contains("x.swap."), "{}", trace);
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{assert_failure, assert_success, stderr, stdout, Scratch};
+
+/// The JSON intent record of a location swap of `a/x` and `b/y`, through `a/x.swap.tmp`.
+fn intent(scratch: &Scratch, id: &str) -> String {
+    let path = |relative: &str| scratch.path(relative).display().to_string();
+    format!(
+        "{{\"version\":1,\"record\":\"intent\",\"id\":\"{}\",\"timestamp\":\"2024-05-01T13:37:00Z\",\
+         \"path1\":\"{}\",\"path2\":\"{}\",\"dest1\":\"{}\",\"dest2\":\"{}\",\"temp\":\"{}\"}}\n",
+        id, path("a/x"), path("b/y"), path("b/x"), path("a/y"), path("a/x.swap.tmp"),
+    )
+}
+
+fn recover(scratch: &Scratch, journal: &Path) -> std::process::Output {
+    scratch.swap(["recover-journal".as_ref(), journal.as_os_str()])
+}
+
+#[test]
+fn crash_before_the_first_rename_leaves_nothing_to_do() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+    let journal = scratch.file("journal", &intent(&scratch, "1"));
+
+    let output = recover(&scratch, &journal);
+    assert_success(&output);
+    assert!(stdout(&output).contains("are consistent, nothing to do"));
+    assert_eq!(scratch.read("a/x"), "x");
+    assert_eq!(scratch.read("b/y"), "y");
+    assert!(scratch.read("journal").ends_with("{\"version\":1,\"record\":\"recovered\",\"id\":\"1\"}\n"));
+}
+
+#[test]
+fn crash_after_the_first_rename_is_rolled_back() {
+    let scratch = Scratch::new();
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("b/y", "y");
+    let journal = scratch.file("journal", &intent(&scratch, "1"));
+
+    let output = recover(&scratch, &journal);
+    assert_success(&output);
+    assert!(stdout(&output).contains("Rolled back the swap"));
+    assert_eq!(scratch.list("a"), ["x"]);
+    assert_eq!(scratch.read("a/x"), "x");
+    assert_eq!(scratch.read("b/y"), "y");
+}
+
+#[test]
+fn crash_after_the_second_rename_is_completed() {
+    let scratch = Scratch::new();
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("a/y", "y");
+    scratch.dir("b");
+    let journal = scratch.file("journal", &intent(&scratch, "1"));
+
+    let output = recover(&scratch, &journal);
+    assert_success(&output);
+    assert!(stdout(&output).contains("Completed the swap"));
+    assert_eq!(scratch.read("b/x"), "x");
+    assert_eq!(scratch.list("a"), ["y"]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn completing_across_filesystems_copies_the_temporary_entry() {
+    let Some((scratch, other)) = common::across_filesystems() else { return };
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("a/y", "y");
+    other.dir("b");
+    let record = intent(&scratch, "1").replace(&scratch.path("b").display().to_string(), &other.path("b").display().to_string());
+    let journal = scratch.file("journal", &record);
+
+    let output = recover(&scratch, &journal);
+    assert_success(&output);
+    assert_eq!(other.read("b/x"), "x");
+    assert_eq!(scratch.list("a"), ["y"]);
+}
+
+#[test]
+fn committed_and_aborted_swaps_are_not_pending() {
+    let scratch = Scratch::new();
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("b/y", "y");
+    let journal = scratch.file("journal", &format!(
+        "{}{{\"version\":1,\"record\":\"commit\",\"id\":\"1\"}}\n{}1\tabort\t2\n",
+        intent(&scratch, "1"), intent(&scratch, "2"),
+    ));
+
+    let output = recover(&scratch, &journal);
+    assert_success(&output);
+    assert!(stdout(&output).contains("No interrupted swap"));
+    assert_eq!(scratch.list("a"), ["x.swap.tmp"]);
+}
+
+#[test]
+fn tab_separated_intents_are_recovered() {
+    let scratch = Scratch::new();
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("b/y", "y");
+    let path = |relative: &str| scratch.path(relative).display().to_string();
+    let record = format!("1\tintent\t1\t2024-05-01T13:37:00Z\t{}\t{}\t{}\t{}\t{}\n", path("a/x"), path("b/y"), path("b/x"), path("a/y"), path("a/x.swap.tmp"));
+    let journal = scratch.file("journal", &record);
+
+    assert_success(&recover(&scratch, &journal));
+    assert_eq!(scratch.read("a/x"), "x");
+    assert!(scratch.read("journal").ends_with("1\trecovered\t1\n"));
+}
+
+#[test]
+fn a_record_cut_short_by_a_crash_is_ignored() {
+    let scratch = Scratch::new();
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("b/y", "y");
+    let first = intent(&scratch, "1");
+    let second = intent(&scratch, "2");
+    let journal = scratch.file("journal", &format!("{}{}", first, &second[..second.len() / 2]));
+
+    let output = recover(&scratch, &journal);
+    assert_success(&output);
+    assert!(stderr(&output).contains("Warning: ignoring line 2 of the journal"));
+    assert_eq!(scratch.read("a/x"), "x");
+}
+
+#[test]
+fn a_record_cut_in_the_middle_of_a_character_is_ignored() {
+    let scratch = Scratch::new();
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("b/y", "y");
+    let journal = scratch.path("journal");
+    let mut content = intent(&scratch, "1").into_bytes();
+    content.extend_from_slice("{\"version\":1,\"record\":\"intent\",\"id\":\"é".as_bytes());
+    content.pop();
+    fs::write(&journal, content).unwrap();
+
+    let output = recover(&scratch, &journal);
+    assert_success(&output);
+    assert!(stderr(&output).contains("Warning: ignoring line 2 of the journal"));
+    assert_eq!(scratch.read("a/x"), "x");
+}
+
+#[test]
+fn an_invalid_record_before_the_last_one_is_refused() {
+    let scratch = Scratch::new();
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("b/y", "y");
+    let journal = scratch.file("journal", &format!("garbage\n{}", intent(&scratch, "1")));
+
+    assert_failure(&recover(&scratch, &journal), "line 1");
+    assert_eq!(scratch.list("a"), ["x.swap.tmp"]);
+}
+
+#[test]
+fn a_newer_format_version_is_refused() {
+    let scratch = Scratch::new();
+    let journal = scratch.file("journal", "{\"version\":2,\"record\":\"commit\",\"id\":\"1\"}\n{\"version\":1,\"record\":\"commit\",\"id\":\"1\"}\n");
+
+    assert_failure(&recover(&scratch, &journal), "is in version 2 of the format");
+}
+
+#[test]
+fn an_existing_entry_is_never_overwritten() {
+    let scratch = Scratch::new();
+    scratch.file("a/x.swap.tmp", "x");
+    scratch.file("a/x", "someone else");
+    scratch.file("b/y", "y");
+    let journal = scratch.file("journal", &intent(&scratch, "1"));
+
+    assert_failure(&recover(&scratch, &journal), "need manual recovery");
+    assert_eq!(scratch.read("a/x"), "someone else");
+    assert_eq!(scratch.read("a/x.swap.tmp"), "x");
+}
+
+#[test]
+fn a_journaled_swap_is_committed() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    assert_success(&scratch.swap(["--journal", "journal", "a/x", "b/y"]));
+    let journal = scratch.read("journal");
+    let lines: Vec<&str> = journal.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", journal);
+    assert!(lines[0].contains("\"record\":\"intent\""));
+    assert!(lines[1].contains("\"record\":\"commit\""));
+    assert!(stdout(&recover(&scratch, &scratch.path("journal"))).contains("No interrupted swap"));
+}
+
+/// Swaps items whose names hold a tab, a newline and a byte that is not valid UTF-8 with
+/// `--journal`, then puts the filesystem and the journal back in the state a crash would have
+/// left them in: recovering must find every path again.
+#[cfg(unix)]
+fn round_trip(format: &str, crashed_after_step: usize) {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let scratch = Scratch::new();
+    let name1 = "tab\there,\nnewline";
+    let name2 = OsStr::from_bytes(b"invalid \xff");
+    scratch.file(&format!("a/{}", name1), "1");
+    scratch.dir("b");
+    fs::write(scratch.path("b").join(name2), "2").unwrap();
+
+    let (path1, path2) = (scratch.path(&format!("a/{}", name1)), scratch.path("b").join(name2));
+    let args = ["--journal".as_ref(), "journal".as_ref(), "--journal-format".as_ref(), OsStr::new(format), path1.as_os_str(), path2.as_os_str()];
+    assert_success(&scratch.swap(args));
+
+    // Drop the commit record, and find the temporary name in the intent.
+    let journal = scratch.read("journal");
+    assert_eq!(journal.lines().count(), 2, "{}", journal);
+    let intent = journal.lines().next().unwrap();
+    fs::write(scratch.path("journal"), format!("{}\n", intent)).unwrap();
+    let uuid = &intent[intent.find(".swap.").unwrap() + 6..][..36];
+    let temp = scratch.path("a").join(format!("{}.swap.{}", name1, uuid));
+    fs::rename(scratch.path(&format!("b/{}", name1)), &temp).unwrap();
+    if crashed_after_step == 1 {
+        fs::rename(scratch.path("a").join(name2), scratch.path("b").join(name2)).unwrap();
+    }
+
+    let output = recover(&scratch, &scratch.path("journal"));
+    assert_success(&output);
+    assert!(temp.symlink_metadata().is_err());
+    if crashed_after_step == 1 {
+        assert_eq!(scratch.read(&format!("a/{}", name1)), "1");
+        assert_eq!(fs::read_to_string(scratch.path("b").join(name2)).unwrap(), "2");
+    } else {
+        assert_eq!(scratch.read(&format!("b/{}", name1)), "1");
+        assert_eq!(fs::read_to_string(scratch.path("a").join(name2)).unwrap(), "2");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn odd_names_round_trip_through_json_lines() {
+    round_trip("jsonl", 1);
+    round_trip("jsonl", 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn odd_names_round_trip_through_tab_separated_fields() {
+    round_trip("tsv", 1);
+    round_trip("tsv", 2);
+}
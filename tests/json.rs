@@ -0,0 +1,72 @@
+mod common;
+
+use common::{assert_success, stdout, Scratch};
+
+/// Runs a swap with `--json` and returns the single line it printed.
+fn outcome(scratch: &Scratch, args: &[&str]) -> String {
+    let output = scratch.swap(["--json"].iter().chain(args));
+    assert_success(&output);
+    let printed = stdout(&output);
+    assert_eq!(printed.lines().count(), 1, "{}", printed);
+    printed
+}
+
+#[test]
+fn items_trading_places_are_exchanged() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/x", "other");
+
+    let json = outcome(&scratch, &["a/x", "b/x"]);
+    let (a, b) = (scratch.path("a/x").display().to_string(), scratch.path("b/x").display().to_string());
+    assert!(json.starts_with(&format!(
+        "{{\"version\":1,\"mode\":\"location\",\"moves\":[{{\"from\":\"{a}\",\"to\":\"{b}\"}},{{\"from\":\"{b}\",\"to\":\"{a}\"}}]"
+    )), "{}", json);
+    assert!(json.contains("\"strategy\":\"exchange\",\"exchange_refused\":false,\"copies\":0,\"temp_retries\":0,\"warnings\":[]"), "{}", json);
+}
+
+#[test]
+fn items_moving_elsewhere_are_renamed_through_a_temporary_name() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    let json = outcome(&scratch, &["--name-swap", "a/x", "b/y"]);
+    assert!(json.contains("\"mode\":\"name\""), "{}", json);
+    assert!(json.contains("\"strategy\":\"rename\""), "{}", json);
+}
+
+#[test]
+fn rotations_report_every_move() {
+    let scratch = Scratch::new();
+    scratch.file("x/a", "a");
+    scratch.file("y/b", "b");
+    scratch.file("z/c", "c");
+
+    let json = outcome(&scratch, &["x/a", "y/b", "z/c"]);
+    assert!(json.contains("\"mode\":\"rotation\""), "{}", json);
+    assert_eq!(json.matches("\"from\":").count(), 3, "{}", json);
+}
+
+#[test]
+fn dry_run_prints_the_plan_instead() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    let json = outcome(&scratch, &["--dry-run", "a/x", "b/y"]);
+    assert!(json.contains("\"rollback_safe\":true"), "{}", json);
+    assert!(!json.contains("\"strategy\""), "{}", json);
+    assert_eq!(scratch.list("a"), ["x"]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn copies_across_filesystems_are_counted() {
+    let Some((scratch, other)) = common::across_filesystems() else { return };
+    scratch.file("a/x", "x");
+    let y = other.file("b/y", "y");
+
+    let json = outcome(&scratch, &["a/x", y.to_str().unwrap()]);
+    assert!(json.contains("\"strategy\":\"rename\",\"exchange_refused\":false,\"copies\":2"), "{}", json);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{assert_success, Scratch};
+
+/// The value of the sample `name` in the metrics file `relative`.
+fn sample(scratch: &Scratch, relative: &str, name: &str) -> u64 {
+    let metrics = scratch.read(relative);
+    let line = metrics.lines().find(|line| line.split(' ').next() == Some(name)).unwrap_or_else(|| panic!("no {} in {}", name, metrics));
+    line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+/// The streaming verification hashes the copy as it is written and reads it back once: every
+/// byte copied is verified exactly once, not read twice more.
+#[cfg(target_os = "linux")]
+#[test]
+fn a_verified_copy_reads_each_byte_back_once() {
+    let Some((scratch, other)) = common::across_filesystems() else { return };
+    scratch.file("a/x", &"x".repeat(300_000));
+    scratch.file("a/tree/one", &"1".repeat(70_000));
+    scratch.file("a/tree/two", &"2".repeat(5_000));
+    let y = other.file("b/y", &"y".repeat(123_456));
+
+    assert_success(&scratch.swap(["--verify".as_ref(), "--metrics-file".as_ref(), "first.prom".as_ref(), scratch.path("a/x").as_os_str(), y.as_os_str()]));
+    assert_eq!(sample(&scratch, "first.prom", "swap_bytes_copied_total"), 423_456);
+    assert_eq!(sample(&scratch, "first.prom", "swap_bytes_verified_total"), 423_456);
+
+    let x = other.path("b/x");
+    assert_success(&scratch.swap(["--verify".as_ref(), "--metrics-file".as_ref(), "second.prom".as_ref(), scratch.path("a/tree").as_os_str(), x.as_os_str()]));
+    assert_eq!(sample(&scratch, "second.prom", "swap_bytes_copied_total"), 375_000);
+    assert_eq!(sample(&scratch, "second.prom", "swap_bytes_verified_total"), 375_000);
+}
+
+/// An anonymous copy is read back through its own descriptor, before it is linked into place.
+#[cfg(target_os = "linux")]
+#[test]
+fn an_anonymous_copy_is_verified_before_it_is_linked() {
+    let Some((scratch, other)) = common::across_filesystems() else { return };
+    scratch.file("a/x", &"x".repeat(200_000));
+    let y = other.file("b/y", &"y".repeat(1_000));
+
+    let args = ["--verify".as_ref(), "--concurrency-safe".as_ref(), "--metrics-file".as_ref(), "metrics.prom".as_ref()];
+    assert_success(&scratch.swap(args.into_iter().chain([scratch.path("a/x").as_os_str(), y.as_os_str()])));
+
+    assert_eq!(sample(&scratch, "metrics.prom", "swap_bytes_verified_total"), 201_000);
+    assert_eq!(other.read("b/x"), "x".repeat(200_000));
+    assert_eq!(scratch.read("a/y"), "y".repeat(1_000));
+}
+
+/// Renamed items have no copy to hash along: they are hashed before and after the swap.
+#[test]
+fn verified_renames_hash_the_items_before_and_after() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", &"x".repeat(1_000));
+    scratch.file("b/y", &"y".repeat(234));
+
+    assert_success(&scratch.swap(["--verify", "--metrics-file", "metrics.prom", "a/x", "b/y"]));
+
+    assert_eq!(sample(&scratch, "metrics.prom", "swap_bytes_copied_total"), 0);
+    assert_eq!(sample(&scratch, "metrics.prom", "swap_bytes_verified_total"), 2 * 1_234);
+}
+
+#[test]
+fn nothing_is_verified_without_verify() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    assert_success(&scratch.swap(["--metrics-file", "metrics.prom", "a/x", "b/y"]));
+
+    assert_eq!(sample(&scratch, "metrics.prom", "swap_bytes_verified_total"), 0);
+    assert_eq!(sample(&scratch, "metrics.prom", "swap_swaps_total"), 1);
+}
+
+#[test]
+fn counters_add_up_across_runs() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    assert_success(&scratch.swap(["--metrics-file", "metrics.prom", "a/x", "b/y"]));
+    assert_success(&scratch.swap(["--metrics-file", "metrics.prom", "a/y", "b/x"]));
+
+    assert_eq!(sample(&scratch, "metrics.prom", "swap_swaps_total"), 2);
+}
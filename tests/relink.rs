@@ -0,0 +1,78 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use common::{assert_success, stdout, Scratch};
+
+/// The symlink farm of the README.
+fn farm() -> Scratch {
+    let scratch = Scratch::new();
+    scratch.file("data/web", "web");
+    scratch.file("data/db", "db");
+    scratch.dir("a");
+    scratch.dir("b/deep");
+    symlink(scratch.path("data/web"), scratch.path("a/web")).unwrap();
+    symlink("../../data/db", scratch.path("b/deep/db")).unwrap();
+    scratch
+}
+
+fn target(scratch: &Scratch, link: &str) -> PathBuf {
+    fs::read_link(scratch.path(link)).unwrap()
+}
+
+#[test]
+fn swapped_symlinks_keep_pointing_at_the_same_entries() {
+    let scratch = farm();
+
+    assert_success(&scratch.swap(["--no-dereference", "--relativize-targets", "a/web", "b/deep/db"]));
+
+    assert_eq!(target(&scratch, "a/db"), Path::new("../data/db"));
+    assert_eq!(target(&scratch, "b/deep/web"), Path::new("../../data/web"));
+    assert_eq!(scratch.read("a/db"), "db");
+    assert_eq!(scratch.read("b/deep/web"), "web");
+}
+
+#[test]
+fn without_the_option_targets_are_kept_as_written() {
+    let scratch = farm();
+
+    assert_success(&scratch.swap(["--no-dereference", "a/web", "b/deep/db"]));
+
+    assert_eq!(target(&scratch, "a/db"), Path::new("../../data/db"));
+    assert_eq!(target(&scratch, "b/deep/web"), scratch.path("data/web"));
+}
+
+#[test]
+fn absolute_targets_outside_the_shared_directory_are_left_alone() {
+    let scratch = farm();
+    symlink("/etc/hostname", scratch.path("a/host")).unwrap();
+
+    assert_success(&scratch.swap(["--no-dereference", "--relativize-targets", "a/host", "b/deep/db"]));
+
+    assert_eq!(target(&scratch, "b/deep/host"), Path::new("/etc/hostname"));
+    assert_eq!(target(&scratch, "a/db"), Path::new("../data/db"));
+}
+
+#[test]
+fn dry_run_lists_the_rewrites_without_doing_them() {
+    let scratch = farm();
+
+    let output = scratch.swap(["--dry-run", "--no-dereference", "--relativize-targets", "a/web", "b/deep/db"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("Would rewrite the target of"), "{}", stdout(&output));
+    assert_eq!(target(&scratch, "b/deep/db"), Path::new("../../data/db"));
+}
+
+#[test]
+fn no_temporary_link_is_left_behind() {
+    let scratch = farm();
+
+    assert_success(&scratch.swap(["--no-dereference", "--relativize-targets", "a/web", "b/deep/db"]));
+
+    assert_eq!(scratch.list("a"), ["db"]);
+    assert_eq!(scratch.list("b/deep"), ["web"]);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::{assert_failure, assert_success, Scratch};
+
+#[test]
+fn name_rotation_keeps_every_item_in_its_directory() {
+    let scratch = Scratch::new();
+    scratch.file("x/a", "a");
+    scratch.file("y/b", "b");
+    scratch.file("z/c", "c");
+
+    assert_success(&scratch.swap(["--name-swap", "x/a", "y/b", "z/c"]));
+
+    assert_eq!(scratch.list("x"), ["b"]);
+    assert_eq!(scratch.list("y"), ["c"]);
+    assert_eq!(scratch.list("z"), ["a"]);
+    assert_eq!(scratch.read("x/b"), "a");
+    assert_eq!(scratch.read("y/c"), "b");
+    assert_eq!(scratch.read("z/a"), "c");
+}
+
+#[test]
+fn location_rotation_moves_every_item_to_the_next_directory() {
+    let scratch = Scratch::new();
+    scratch.file("x/a", "a");
+    scratch.file("y/b", "b");
+    scratch.file("z/c", "c");
+
+    assert_success(&scratch.swap(["x/a", "y/b", "z/c"]));
+
+    assert_eq!(scratch.read("y/a"), "a");
+    assert_eq!(scratch.read("z/b"), "b");
+    assert_eq!(scratch.read("x/c"), "c");
+}
+
+#[test]
+fn reverse_rotates_the_other_way_round() {
+    let scratch = Scratch::new();
+    scratch.file("x/a", "a");
+    scratch.file("y/b", "b");
+    scratch.file("z/c", "c");
+
+    assert_success(&scratch.swap(["--name-swap", "--reverse", "x/a", "y/b", "z/c"]));
+
+    assert_eq!(scratch.read("x/c"), "a");
+    assert_eq!(scratch.read("y/a"), "b");
+    assert_eq!(scratch.read("z/b"), "c");
+}
+
+#[test]
+fn guards_apply_to_every_rotated_item() {
+    let scratch = Scratch::new();
+    scratch.file("x/a", "a");
+    scratch.file("y/b", "b");
+    scratch.dir("z/c");
+
+    assert_failure(&scratch.swap(["--require-same-type", "x/a", "y/b", "z/c"]), "'z/c' is a directory, expected a file");
+    assert_failure(&scratch.swap(["--require-type", "dir", "x/a", "y/b", "z/c"]), "'x/a' is a file, expected a directory");
+    assert_failure(&scratch.swap(["--reserve", "900T", "x/a", "y/b", "z/c"]), "below the reserved margin");
+    assert_eq!(scratch.read("x/a"), "a");
+    assert_eq!(scratch.read("y/b"), "b");
+}
+
+#[test]
+fn all_missing_items_are_reported() {
+    let scratch = Scratch::new();
+    scratch.file("x/a", "a");
+
+    let output = scratch.swap(["x/a", "y/b", "z/c"]);
+    assert_failure(&output, "Path not found: 'y/b'");
+    assert_failure(&output, "Path not found: 'z/c'");
+}
+
+#[test]
+fn two_items_with_the_same_destination_are_refused() {
+    let scratch = Scratch::new();
+    scratch.file("x/a", "a");
+    scratch.file("x/b", "b");
+    scratch.file("z/a", "z");
+
+    assert_failure(&scratch.swap(["x/a", "x/b", "z/a"]), "would move two items to");
+    assert_eq!(scratch.list("x"), ["a", "b"]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn location_rotation_across_filesystems_is_refused() {
+    let Some((scratch, other)) = common::across_filesystems() else { return };
+    scratch.file("x/a", "a");
+    scratch.file("y/b", "b");
+    let c = other.file("z/c", "c");
+
+    let output = scratch.swap(["x/a".as_ref(), "y/b".as_ref(), c.as_os_str()]);
+    assert_failure(&output, "rotations only rename, they do not copy");
+    assert_eq!(scratch.read("x/a"), "a");
+    assert_eq!(other.read("z/c"), "c");
+}
@@ -0,0 +1,104 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+
+use common::{assert_success, stderr, stdout, Scratch};
+
+const COMPOSED: &str = "caf\u{e9}";
+const DECOMPOSED: &str = "cafe\u{301}";
+
+fn inode(scratch: &Scratch, relative: &str) -> u64 {
+    fs::symlink_metadata(scratch.path(relative)).unwrap().ino()
+}
+
+#[test]
+fn hard_links_in_one_directory_are_a_no_op() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    fs::hard_link(scratch.path("a/x"), scratch.path("a/y")).unwrap();
+
+    for args in [&["a/x", "a/y"][..], &["--name-swap", "a/x", "a/y"]] {
+        let output = scratch.swap(args);
+        assert_success(&output);
+        let said = format!("{}{}", stdout(&output), stderr(&output));
+        assert!(said.contains("are hard links to the same file in the same directory, swapping them changes nothing"), "{}", said);
+        assert!(!said.contains("Error"), "{}", said);
+    }
+    assert_eq!(scratch.list("a"), ["x", "y"]);
+    assert_eq!(inode(&scratch, "a/x"), inode(&scratch, "a/y"));
+}
+
+#[test]
+fn hard_links_in_two_directories_are_swapped() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.dir("b");
+    fs::hard_link(scratch.path("a/x"), scratch.path("b/y")).unwrap();
+
+    assert_success(&scratch.swap(["a/x", "b/y"]));
+
+    assert_eq!(scratch.list("a"), ["y"]);
+    assert_eq!(scratch.list("b"), ["x"]);
+}
+
+#[test]
+fn hard_links_are_rotated_like_other_items() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("a/z", "z");
+    fs::hard_link(scratch.path("a/x"), scratch.path("a/y")).unwrap();
+    let linked = inode(&scratch, "a/x");
+
+    assert_success(&scratch.swap(["--name-swap", "a/x", "a/y", "a/z"]));
+
+    assert_eq!(inode(&scratch, "a/y"), linked);
+    assert_eq!(inode(&scratch, "a/z"), linked);
+    assert_eq!(scratch.read("a/x"), "z");
+}
+
+/// On a filesystem that does not normalize names, both spellings are distinct entries.
+#[cfg(target_os = "linux")]
+#[test]
+fn composed_and_decomposed_names_are_distinct_entries_on_linux() {
+    let scratch = Scratch::new();
+    scratch.file(&format!("a/{}", COMPOSED), "composed");
+    scratch.file(&format!("a/{}", DECOMPOSED), "decomposed");
+
+    assert_success(&scratch.swap(["--name-swap", &format!("a/{}", COMPOSED), &format!("a/{}", DECOMPOSED)]));
+
+    assert_eq!(scratch.read(&format!("a/{}", COMPOSED)), "decomposed");
+    assert_eq!(scratch.read(&format!("a/{}", DECOMPOSED)), "composed");
+}
+
+/// APFS and HFS+ normalize names: both spellings designate the same entry.
+#[cfg(target_os = "macos")]
+#[test]
+fn composed_and_decomposed_names_are_the_same_entry_on_macos() {
+    let scratch = Scratch::new();
+    scratch.file(&format!("a/{}", COMPOSED), "composed");
+    if fs::symlink_metadata(scratch.path(&format!("a/{}", DECOMPOSED))).is_err() {
+        return;
+    }
+
+    let output = scratch.swap(["--name-swap", &format!("a/{}", COMPOSED), &format!("a/{}", DECOMPOSED)]);
+    common::assert_failure(&output, "two Unicode normalizations");
+    assert_eq!(scratch.read(&format!("a/{}", COMPOSED)), "composed");
+}
+
+/// Case-insensitive volumes, the default on macOS: `Foo` and `foo` designate the same entry.
+#[cfg(target_os = "macos")]
+#[test]
+fn names_differing_by_case_are_the_same_entry_on_case_insensitive_volumes() {
+    let scratch = Scratch::new();
+    scratch.file("a/Foo", "foo");
+    if fs::symlink_metadata(scratch.path("a/foo")).is_err() {
+        return;
+    }
+
+    let output = scratch.swap(["--name-swap", "a/Foo", "a/foo"]);
+    common::assert_failure(&output, "only differ by case");
+    assert_eq!(scratch.list("a"), ["Foo"]);
+}
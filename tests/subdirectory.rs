@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{assert_failure, Scratch};
+
+const REFUSED: &str = "Cannot swap a directory with its own subdirectory";
+
+#[test]
+fn a_subdirectory_reached_through_an_unresolved_symlink_is_refused() {
+    let scratch = Scratch::new();
+    scratch.file("a/sub/file", "file");
+    std::os::unix::fs::symlink("a", scratch.path("link")).unwrap();
+
+    // With `--no-dereference`, `link/sub` does not start with the path of `a`.
+    assert_failure(&scratch.swap(["--no-dereference", "a", "link/sub"]), REFUSED);
+    assert_failure(&scratch.swap(["--no-dereference", "link/sub", "a"]), REFUSED);
+    assert_failure(&scratch.swap(["--no-dereference", "--name-swap", "a", "link/sub"]), REFUSED);
+    assert_eq!(scratch.read("a/sub/file"), "file");
+}
+
+#[test]
+fn a_rotated_subdirectory_reached_through_a_symlink_is_refused() {
+    let scratch = Scratch::new();
+    scratch.file("a/sub/file", "file");
+    scratch.file("b/item", "item");
+    std::os::unix::fs::symlink("a", scratch.path("link")).unwrap();
+
+    assert_failure(&scratch.swap(["--no-dereference", "a", "b/item", "link/sub"]), REFUSED);
+    assert_eq!(scratch.read("a/sub/file"), "file");
+}
+
+/// Needs the privilege to mount: skipped otherwise.
+#[cfg(target_os = "linux")]
+#[test]
+fn a_subdirectory_reached_through_a_bind_mount_is_refused() {
+    use std::process::Command;
+
+    let scratch = Scratch::new();
+    scratch.file("a/sub/file", "file");
+    let bind = scratch.dir("bind");
+    let mounted = Command::new("mount").arg("--bind").arg(scratch.path("a")).arg(&bind).output();
+    if !mounted.is_ok_and(|output| output.status.success()) {
+        return;
+    }
+
+    let output = scratch.swap(["a", "bind/sub"]);
+    Command::new("umount").arg(&bind).status().unwrap();
+    assert_failure(&output, REFUSED);
+    assert_eq!(scratch.read("a/sub/file"), "file");
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{assert_failure, assert_success, stdout, Scratch};
+
+#[test]
+fn location_swap_moves_each_item_to_the_other_directory() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    assert_success(&scratch.swap(["a/x", "b/y"]));
+
+    assert_eq!(scratch.list("a"), ["y"]);
+    assert_eq!(scratch.list("b"), ["x"]);
+    assert_eq!(scratch.read("a/y"), "y");
+    assert_eq!(scratch.read("b/x"), "x");
+}
+
+#[test]
+fn name_swap_keeps_each_item_in_its_directory() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    assert_success(&scratch.swap(["--name-swap", "a/x", "b/y"]));
+
+    assert_eq!(scratch.read("a/y"), "x");
+    assert_eq!(scratch.read("b/x"), "y");
+}
+
+#[test]
+fn items_trading_places_exchange_their_contents() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("a/d/inner", "inner");
+
+    assert_success(&scratch.swap(["--name-swap", "a/x", "a/d"]));
+    assert_eq!(scratch.read("a/d"), "x");
+    assert_eq!(scratch.read("a/x/inner"), "inner");
+
+    scratch.file("b/x", "other");
+    assert_success(&scratch.swap(["a/x", "b/x"]));
+    assert_eq!(scratch.read("a/x"), "other");
+    assert_eq!(scratch.read("b/x/inner"), "inner");
+}
+
+#[test]
+fn both_modes_swap_directories() {
+    let scratch = Scratch::new();
+    scratch.file("a/one/file", "1");
+    scratch.file("b/two/file", "2");
+
+    assert_success(&scratch.swap(["a/one", "b/two"]));
+    assert_eq!(scratch.read("a/two/file"), "2");
+    assert_eq!(scratch.read("b/one/file"), "1");
+
+    assert_success(&scratch.swap(["--name-swap", "a/two", "b/one"]));
+    assert_eq!(scratch.read("a/one/file"), "2");
+    assert_eq!(scratch.read("b/two/file"), "1");
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+
+    let output = scratch.swap(["--dry-run", "a/x", "b/y"]);
+    assert_success(&output);
+    assert!(!stdout(&output).is_empty());
+    assert_eq!(scratch.list("a"), ["x"]);
+    assert_eq!(scratch.list("b"), ["y"]);
+}
+
+#[test]
+fn the_same_path_twice_is_refused() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+
+    assert_failure(&scratch.swap(["a/x", "a/x"]), "The two paths are identical");
+    assert_failure(&scratch.swap(["a/x", "./a/../a/x"]), "The two paths are identical");
+    assert_failure(&scratch.swap(["--name-swap", "a/x", "a/x"]), "The two paths are identical");
+}
+
+#[test]
+fn a_missing_path_is_refused() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+
+    assert_failure(&scratch.swap(["a/x", "b/y"]), "Path not found: 'b/y'");
+    assert_failure(&scratch.swap(["--name-swap", "b/y", "a/x"]), "Path not found: 'b/y'");
+    assert_eq!(scratch.read("a/x"), "x");
+}
+
+#[test]
+fn a_directory_and_its_subdirectory_are_refused() {
+    let scratch = Scratch::new();
+    scratch.file("a/d/inner", "inner");
+
+    assert_failure(&scratch.swap(["a", "a/d"]), "Cannot swap a directory with its own subdirectory");
+    assert_failure(&scratch.swap(["--name-swap", "a/d", "a"]), "Cannot swap a directory with its own subdirectory");
+    assert_eq!(scratch.read("a/d/inner"), "inner");
+}
+
+#[test]
+fn an_unrelated_destination_is_not_overwritten_without_confirmation() {
+    let scratch = Scratch::new();
+    scratch.file("a/x", "x");
+    scratch.file("b/y", "y");
+    scratch.file("b/x", "other");
+
+    assert_failure(&scratch.swap(["a/x", "b/y"]), "would be overwritten");
+    assert_eq!(scratch.read("a/x"), "x");
+    assert_eq!(scratch.read("b/x"), "other");
+}
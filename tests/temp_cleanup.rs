@@ -0,0 +1,75 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use common::{across_filesystems, assert_failure};
+
+/// The entries of `dir`, recursively, whose name holds the `.swap.` of temporary names.
+fn leftovers(dir: &Path) -> Vec<String> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().contains(".swap.") {
+            found.push(path.display().to_string());
+        }
+        if entry.file_type().unwrap().is_dir() {
+            found.extend(leftovers(&path));
+        }
+    }
+    found
+}
+
+#[test]
+fn a_copy_failing_halfway_leaves_no_temporary_entry() {
+    let Some((scratch, other)) = across_filesystems() else { return };
+    scratch.file("a/tree/first", "1");
+    scratch.file("a/tree/nested/second", "2");
+    // A socket cannot be copied, so the copy of the tree fails once it was started.
+    let _socket = UnixListener::bind(scratch.path("a/tree/nested/socket")).unwrap();
+    let y = other.file("b/y", "y");
+
+    let output = scratch.swap(["a/tree".as_ref(), y.as_os_str()]);
+    assert_failure(&output, "unsupported file type");
+
+    assert_eq!(leftovers(scratch.root()), Vec::<String>::new());
+    assert_eq!(leftovers(other.root()), Vec::<String>::new());
+    assert_eq!(scratch.list("a"), ["tree"]);
+    assert_eq!(scratch.read("a/tree/nested/second"), "2");
+    assert_eq!(other.list("b"), ["y"]);
+}
+
+#[test]
+fn a_swap_interrupted_during_a_copy_leaves_no_temporary_entry() {
+    let Some((scratch, other)) = across_filesystems() else { return };
+    scratch.file("a/x", "x");
+    let y = other.file("b/y", &"y".repeat(4 << 20));
+
+    // Throttled, the copy of `y` takes long enough to be interrupted while it runs.
+    let child = Command::new(env!("CARGO_BIN_EXE_swap"))
+        .args(["--bwlimit".as_ref(), "512K".as_ref(), scratch.path("a/x").as_os_str(), y.as_os_str()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The item renamed to its temporary name, and the copy being written.
+    let started = Instant::now();
+    while leftovers(scratch.root()).len() < 2 {
+        assert!(started.elapsed() < Duration::from_secs(10), "the copy did not start");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    assert!(common::stderr(&output).contains("cancelled"), "{}", common::stderr(&output));
+
+    assert_eq!(leftovers(scratch.root()), Vec::<String>::new());
+    assert_eq!(leftovers(other.root()), Vec::<String>::new());
+    assert_eq!(scratch.read("a/x"), "x");
+    assert_eq!(other.list("b"), ["y"]);
+}
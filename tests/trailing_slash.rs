@@ -0,0 +1,72 @@
+mod common;
+
+use common::{assert_failure, assert_success, Scratch};
+
+#[test]
+fn a_trailing_slash_swaps_with_the_namesake_inside_the_directory() {
+    let scratch = Scratch::new();
+    scratch.file("staging/config", "new");
+    scratch.file("production/config", "old");
+
+    assert_success(&scratch.swap(["staging/config", "production/"]));
+
+    assert_eq!(scratch.read("production/config"), "new");
+    assert_eq!(scratch.read("staging/config"), "old");
+    assert_eq!(scratch.list("production"), ["config"]);
+}
+
+#[test]
+fn the_slash_may_be_on_either_side() {
+    let scratch = Scratch::new();
+    scratch.file("staging/config", "new");
+    scratch.file("production/config", "old");
+
+    assert_success(&scratch.swap(["production/", "staging/config"]));
+
+    assert_eq!(scratch.read("production/config"), "new");
+    assert_eq!(scratch.read("staging/config"), "old");
+}
+
+#[test]
+fn without_the_slash_the_directory_itself_is_swapped() {
+    let scratch = Scratch::new();
+    scratch.file("staging/config", "new");
+    scratch.file("production/config", "old");
+
+    assert_success(&scratch.swap(["--name-swap", "staging/config", "production"]));
+
+    assert_eq!(scratch.read("staging/production"), "new");
+    assert_eq!(scratch.read("config/config"), "old");
+}
+
+#[test]
+fn with_both_slashes_the_directories_are_swapped() {
+    let scratch = Scratch::new();
+    scratch.file("staging/config", "new");
+    scratch.file("production/config", "old");
+
+    assert_success(&scratch.swap(["--name-swap", "staging/", "production/"]));
+
+    assert_eq!(scratch.read("production/config"), "new");
+    assert_eq!(scratch.read("staging/config"), "old");
+}
+
+#[test]
+fn a_missing_namesake_is_refused() {
+    let scratch = Scratch::new();
+    scratch.file("staging/config", "new");
+    scratch.dir("production");
+
+    assert_failure(&scratch.swap(["staging/config", "production/"]), "Path not found: 'production/config'");
+    assert_eq!(scratch.read("staging/config"), "new");
+}
+
+#[test]
+fn a_trailing_slash_on_a_file_is_refused() {
+    let scratch = Scratch::new();
+    scratch.file("staging/config", "new");
+    scratch.file("production/config", "old");
+
+    assert_failure(&scratch.swap(["staging/config", "production/config/"]), "Not a directory");
+    assert_eq!(scratch.read("production/config"), "old");
+}